@@ -0,0 +1,229 @@
+//! The error returned when a borrow conflicts with an existing borrow.
+
+use core::fmt::{Display, Formatter};
+use core::panic::Location;
+
+use crate::state::HeldBorrows;
+use crate::BorrowSnapshot;
+#[cfg(feature = "std")]
+use std::{backtrace::Backtrace, boxed::Box, sync::Arc};
+
+/// The kind of borrow that was requested when a [`BorrowError`] occurred.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum BorrowRequest {
+    /// A shared borrow, as in `read`.
+    Shared,
+    /// An upgradable borrow, as in `upgradable_read`.
+    Upgradable,
+    /// An exclusive borrow, as in `write` or `lock`.
+    Exclusive,
+    /// Upgrading the upgradable borrow to an exclusive borrow.
+    ///
+    /// Unlike a real lock, this can not wait for the other readers to finish.
+    /// They are on the same thread, so a real lock would deadlock.
+    Upgrade,
+    /// A non-recursive shared borrow while another shared borrow is active.
+    ///
+    /// This is only an error with the `strict-recursion` feature.
+    RecursiveShared,
+}
+
+/// The kind of borrow that currently holds a lock.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum BorrowKind {
+    /// One or more shared borrows.
+    Shared,
+    /// One or more shared borrows, one of which is upgradable.
+    Upgradable,
+    /// An exclusive borrow.
+    Exclusive,
+}
+
+/// A complete report of a borrow that conflicted with an existing borrow.
+///
+/// This is the single-threaded equivalent of a deadlock,
+/// similar to the [`BorrowError`](core::cell::BorrowError) of a `RefCell`.
+///
+/// The error includes a full [BorrowSnapshot] of the lock, so it is fairly large.
+/// Conflicts are expected to be rare, so the `*_err` methods return it inline
+/// instead of boxing it, which would require `alloc`.
+#[derive(Clone, Debug)]
+pub struct BorrowError {
+    /// The kind of borrow that was requested.
+    request: BorrowRequest,
+    /// The location where the conflicting borrow was requested.
+    requested_location: &'static Location<'static>,
+    /// The borrows that currently hold the lock.
+    held: BorrowSnapshot,
+    /// Whether the lock is being used as a [CellMutex](crate::raw::CellMutex).
+    is_mutex: bool,
+    /// The backtraces of the borrows that currently hold the lock,
+    /// along with the location of the corresponding borrow.
+    #[cfg(feature = "std")]
+    held_backtraces: Box<[(&'static Location<'static>, Arc<Backtrace>)]>,
+}
+
+impl BorrowError {
+    #[inline]
+    pub(crate) fn new(
+        request: BorrowRequest,
+        requested_location: &'static Location<'static>,
+        held: BorrowSnapshot,
+    ) -> Self {
+        debug_assert!(!held.is_unused());
+        BorrowError {
+            request,
+            requested_location,
+            held,
+            is_mutex: false,
+            #[cfg(feature = "std")]
+            held_backtraces: Box::new([]),
+        }
+    }
+
+    #[cfg(all(debug_location, feature = "std"))]
+    #[inline]
+    pub(crate) fn with_backtraces(
+        self,
+        held_backtraces: Box<[(&'static Location<'static>, Arc<Backtrace>)]>,
+    ) -> Self {
+        BorrowError {
+            held_backtraces,
+            ..self
+        }
+    }
+
+    /// Use [CellMutex](crate::raw::CellMutex) wording instead of talking about borrows.
+    #[inline]
+    pub(crate) fn for_mutex(self) -> Self {
+        BorrowError {
+            is_mutex: true,
+            ..self
+        }
+    }
+
+    /// The kind of borrow that was requested.
+    #[inline]
+    pub fn request(&self) -> BorrowRequest {
+        self.request
+    }
+
+    /// The location where the conflicting borrow was requested.
+    #[inline]
+    pub fn requested_location(&self) -> &'static Location<'static> {
+        self.requested_location
+    }
+
+    /// The kind of borrow that currently holds the lock.
+    #[inline]
+    pub fn held_kind(&self) -> BorrowKind {
+        self.held
+            .kind()
+            .expect("A conflicting lock must be borrowed")
+    }
+
+    /// A snapshot of the borrows that currently hold the lock.
+    #[inline]
+    pub fn held(&self) -> &BorrowSnapshot {
+        &self.held
+    }
+
+    /// The number of borrows that currently hold the lock.
+    ///
+    /// For a shared borrow, this is the number of readers.
+    #[inline]
+    pub fn held_count(&self) -> usize {
+        self.held.count()
+    }
+
+    /// The locations of the borrows that currently hold the lock,
+    /// in the order they were acquired.
+    ///
+    /// This is empty unless the `debug-location` feature is enabled.
+    /// It may also contain fewer locations than [`Self::held_count`],
+    /// because only a limited number of locations are tracked.
+    #[inline]
+    pub fn held_locations(&self) -> impl Iterator<Item = &'static Location<'static>> + '_ {
+        self.held.locations()
+    }
+
+    /// The backtraces of the borrows that currently hold the lock,
+    /// along with the location of the corresponding borrow.
+    ///
+    /// Backtraces are only captured in debug mode,
+    /// when enabled by the `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` environment variables.
+    #[cfg(feature = "std")]
+    pub fn held_backtraces(
+        &self,
+    ) -> impl Iterator<Item = (&'static Location<'static>, &Backtrace)> + '_ {
+        self.held_backtraces
+            .iter()
+            .map(|(location, backtrace)| (*location, &**backtrace))
+    }
+
+    /// Whether the conflict occurred in a mutex, rather than a reader-writer lock.
+    #[inline]
+    pub fn is_mutex(&self) -> bool {
+        self.is_mutex
+    }
+
+    /// Panic with this error as the message.
+    ///
+    /// With the `std` feature, this includes the backtraces
+    /// of the borrows that are still held, if any were captured.
+    #[cold]
+    #[track_caller]
+    pub fn panic(&self) -> ! {
+        #[cfg(feature = "std")]
+        if self.held_backtraces().next().is_some() {
+            use core::fmt::Write;
+            let mut message = std::string::ToString::to_string(self);
+            for (location, backtrace) in self.held_backtraces() {
+                write!(
+                    message,
+                    "\n\nBacktrace of the borrow at {location}:\n{backtrace}"
+                )
+                .unwrap();
+            }
+            panic!("{message}")
+        }
+        panic!("{self}")
+    }
+}
+impl Display for BorrowError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let requested = if self.is_mutex {
+            "lock mutex"
+        } else {
+            match self.request {
+                BorrowRequest::Shared => "borrow",
+                BorrowRequest::Upgradable => "upgradably borrow",
+                BorrowRequest::Exclusive => "exclusively borrow",
+                BorrowRequest::Upgrade => "upgrade borrow",
+                BorrowRequest::RecursiveShared => "recursively borrow",
+            }
+        };
+        let held = HeldBorrows {
+            snapshot: &self.held,
+            is_mutex: self.is_mutex,
+        };
+        write!(
+            f,
+            "Unable to {requested} at {}: Already {held}",
+            self.requested_location
+        )?;
+        match self.request {
+            BorrowRequest::Upgrade => f.write_str(
+                " (waiting for the other readers to finish would deadlock a real lock)",
+            )?,
+            BorrowRequest::RecursiveShared => f.write_str(
+                " (this would deadlock a real lock if a writer is waiting, \
+                use a recursive read instead)",
+            )?,
+            BorrowRequest::Shared | BorrowRequest::Upgradable | BorrowRequest::Exclusive => {}
+        }
+        Ok(())
+    }
+}
+impl core::error::Error for BorrowError {}
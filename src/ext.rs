@@ -0,0 +1,120 @@
+//! Safe access to the state of the raw cell locks, for the [lock_api] type aliases.
+//!
+//! The [lock_api] types only expose the raw lock through an `unsafe` method,
+//! so the extra methods of [crate::raw::CellRwLock] would otherwise require `unsafe` code:
+//! ```
+//! use refcell_lock_api::{CellLockExt, CellRwLock};
+//!
+//! let lock = CellRwLock::new(7);
+//! let _guard = lock.read();
+//! assert_eq!(lock.borrow_state().count(), 1);
+//! ```
+//!
+//! The wrappers in the [crate::tracked] module have these methods built in.
+
+use crate::BorrowSnapshot;
+
+/// Inspect the state of a cell lock without `unsafe` code.
+pub trait CellLockExt {
+    /// Take a snapshot of the current borrow state of the lock.
+    ///
+    /// See [crate::raw::CellRwLock::borrow_state] for details.
+    fn borrow_state(&self) -> BorrowSnapshot;
+
+    /// Check if the lock is poisoned.
+    ///
+    /// See [crate::raw::CellRwLock::is_poisoned] for details.
+    #[cfg(feature = "std")]
+    fn is_poisoned(&self) -> bool;
+
+    /// Clear the poisoned state of the lock.
+    #[cfg(feature = "std")]
+    fn clear_poison(&self);
+}
+
+impl<T: ?Sized> CellLockExt for crate::CellRwLock<T> {
+    #[inline]
+    fn borrow_state(&self) -> BorrowSnapshot {
+        // SAFETY: Only inspects the lock, without locking or unlocking it
+        unsafe { self.raw() }.borrow_state()
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    fn is_poisoned(&self) -> bool {
+        // SAFETY: Only inspects the lock, without locking or unlocking it
+        unsafe { self.raw() }.is_poisoned()
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    fn clear_poison(&self) {
+        // SAFETY: Clearing the poison flag does not affect the borrows
+        unsafe { self.raw() }.clear_poison()
+    }
+}
+
+impl<T: ?Sized> CellLockExt for crate::CellMutex<T> {
+    #[inline]
+    fn borrow_state(&self) -> BorrowSnapshot {
+        // SAFETY: Only inspects the mutex, without locking or unlocking it
+        unsafe { self.raw() }.borrow_state()
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    fn is_poisoned(&self) -> bool {
+        // SAFETY: Only inspects the mutex, without locking or unlocking it
+        unsafe { self.raw() }.is_poisoned()
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    fn clear_poison(&self) {
+        // SAFETY: Clearing the poison flag does not affect the borrows
+        unsafe { self.raw() }.clear_poison()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CellLockExt;
+    use crate::{BorrowStatus, CellMutex, CellRwLock};
+
+    #[test]
+    fn borrow_state() {
+        let lock = CellRwLock::new(7i32);
+        assert!(lock.borrow_state().is_unused());
+        {
+            let _first = lock.read();
+            let _second = lock.read_recursive();
+            assert_eq!(lock.borrow_state().count(), 2);
+        }
+        let _guard = lock.write();
+        assert_eq!(lock.borrow_state().status(), BorrowStatus::Exclusive);
+
+        let mutex = CellMutex::new(7i32);
+        let _guard = mutex.lock();
+        assert_eq!(mutex.borrow_state().status(), BorrowStatus::Exclusive);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn poisoning() {
+        let lock = CellRwLock::new(7i32);
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _guard = lock.write();
+            panic!("poison");
+        }));
+        assert!(lock.is_poisoned());
+        lock.clear_poison();
+        assert!(!lock.is_poisoned());
+
+        let mutex = CellMutex::new(7i32);
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _guard = mutex.lock();
+            panic!("poison");
+        }));
+        assert!(mutex.is_poisoned());
+    }
+}
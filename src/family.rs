@@ -0,0 +1,301 @@
+//! Write code once, generic over single-threaded and multi-threaded locks.
+//!
+//! A [LockFamily] groups together a mutex, a reader-writer lock,
+//! and the matching shared pointer ([Rc] or [Arc](alloc::sync::Arc)).
+//! A library can then be written as `Foo<F: LockFamily>`,
+//! instead of taking a separate type parameter for each raw lock:
+//! ```
+//! use refcell_lock_api::family::{CellFamily, LockFamily};
+//!
+//! struct Counter<F: LockFamily> {
+//!     count: F::Shared<F::Mutex<u32>>,
+//! }
+//! impl<F: LockFamily> Counter<F> {
+//!     fn new() -> Self {
+//!         Counter { count: F::new_shared(F::new_mutex(0)) }
+//!     }
+//!     fn increment(&self) -> u32 {
+//!         let mut count = F::lock(&self.count);
+//!         *count += 1;
+//!         *count
+//!     }
+//! }
+//!
+//! let counter = Counter::<CellFamily>::new();
+//! assert_eq!(counter.increment(), 1);
+//! ```
+//!
+//! This module requires the `alloc` feature, which is all the [CellFamily] needs.
+//! The `parking_lot` feature enables the `ParkingLotFamily`,
+//! and the `std` feature enables the `StdFamily`.
+
+use alloc::rc::{self, Rc};
+use core::ops::{Deref, DerefMut};
+
+use crate::tracked;
+
+/// A family of lock types, which are either all single-threaded or all multi-threaded.
+///
+/// See the [module documentation](self) for an example.
+pub trait LockFamily {
+    /// A mutex containing a `T`.
+    type Mutex<T>;
+    /// A guard for a locked [Self::Mutex].
+    type MutexGuard<'a, T: 'a>: DerefMut<Target = T>;
+    /// A reader-writer lock containing a `T`.
+    type RwLock<T>;
+    /// A guard for a shared borrow of a [Self::RwLock].
+    type ReadGuard<'a, T: 'a>: Deref<Target = T>;
+    /// A guard for an exclusive borrow of a [Self::RwLock].
+    type WriteGuard<'a, T: 'a>: DerefMut<Target = T>;
+    /// A reference-counted pointer to a `T`, either [Rc] or [Arc](alloc::sync::Arc).
+    type Shared<T>: Deref<Target = T> + Clone;
+    /// A weak reference corresponding to [Self::Shared].
+    type Weak<T>: Clone;
+
+    /// Create a new mutex in the unlocked state.
+    fn new_mutex<T>(val: T) -> Self::Mutex<T>;
+    /// Lock the mutex.
+    #[track_caller]
+    fn lock<T>(mutex: &Self::Mutex<T>) -> Self::MutexGuard<'_, T>;
+    /// Create a new reader-writer lock in the unlocked state.
+    fn new_rwlock<T>(val: T) -> Self::RwLock<T>;
+    /// Acquire a shared borrow of the reader-writer lock.
+    #[track_caller]
+    fn read<T>(lock: &Self::RwLock<T>) -> Self::ReadGuard<'_, T>;
+    /// Acquire an exclusive borrow of the reader-writer lock.
+    #[track_caller]
+    fn write<T>(lock: &Self::RwLock<T>) -> Self::WriteGuard<'_, T>;
+    /// Create a new reference-counted pointer.
+    fn new_shared<T>(val: T) -> Self::Shared<T>;
+    /// Create a weak reference to a reference-counted pointer.
+    fn downgrade<T>(shared: &Self::Shared<T>) -> Self::Weak<T>;
+    /// Upgrade a weak reference, returning `None` if the value has been dropped.
+    fn upgrade<T>(weak: &Self::Weak<T>) -> Option<Self::Shared<T>>;
+}
+
+/// The single-threaded [LockFamily], using the locks in the [tracked] module and [Rc].
+pub enum CellFamily {}
+impl LockFamily for CellFamily {
+    type Mutex<T> = tracked::CellMutex<T>;
+    type MutexGuard<'a, T: 'a> = tracked::CellMutexGuard<'a, T>;
+    type RwLock<T> = tracked::CellRwLock<T>;
+    type ReadGuard<'a, T: 'a> = tracked::CellRwLockReadGuard<'a, T>;
+    type WriteGuard<'a, T: 'a> = tracked::CellRwLockWriteGuard<'a, T>;
+    type Shared<T> = Rc<T>;
+    type Weak<T> = rc::Weak<T>;
+
+    #[inline]
+    fn new_mutex<T>(val: T) -> Self::Mutex<T> {
+        tracked::CellMutex::new(val)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn lock<T>(mutex: &Self::Mutex<T>) -> Self::MutexGuard<'_, T> {
+        mutex.lock()
+    }
+
+    #[inline]
+    fn new_rwlock<T>(val: T) -> Self::RwLock<T> {
+        tracked::CellRwLock::new(val)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn read<T>(lock: &Self::RwLock<T>) -> Self::ReadGuard<'_, T> {
+        lock.read()
+    }
+
+    #[inline]
+    #[track_caller]
+    fn write<T>(lock: &Self::RwLock<T>) -> Self::WriteGuard<'_, T> {
+        lock.write()
+    }
+
+    #[inline]
+    fn new_shared<T>(val: T) -> Self::Shared<T> {
+        Rc::new(val)
+    }
+
+    #[inline]
+    fn downgrade<T>(shared: &Self::Shared<T>) -> Self::Weak<T> {
+        Rc::downgrade(shared)
+    }
+
+    #[inline]
+    fn upgrade<T>(weak: &Self::Weak<T>) -> Option<Self::Shared<T>> {
+        weak.upgrade()
+    }
+}
+
+/// The multi-threaded [LockFamily], using the locks from `parking_lot` and [Arc](alloc::sync::Arc).
+#[cfg(feature = "parking_lot")]
+pub enum ParkingLotFamily {}
+#[cfg(feature = "parking_lot")]
+impl LockFamily for ParkingLotFamily {
+    type Mutex<T> = parking_lot::Mutex<T>;
+    type MutexGuard<'a, T: 'a> = parking_lot::MutexGuard<'a, T>;
+    type RwLock<T> = parking_lot::RwLock<T>;
+    type ReadGuard<'a, T: 'a> = parking_lot::RwLockReadGuard<'a, T>;
+    type WriteGuard<'a, T: 'a> = parking_lot::RwLockWriteGuard<'a, T>;
+    type Shared<T> = alloc::sync::Arc<T>;
+    type Weak<T> = alloc::sync::Weak<T>;
+
+    #[inline]
+    fn new_mutex<T>(val: T) -> Self::Mutex<T> {
+        parking_lot::Mutex::new(val)
+    }
+
+    #[inline]
+    fn lock<T>(mutex: &Self::Mutex<T>) -> Self::MutexGuard<'_, T> {
+        mutex.lock()
+    }
+
+    #[inline]
+    fn new_rwlock<T>(val: T) -> Self::RwLock<T> {
+        parking_lot::RwLock::new(val)
+    }
+
+    #[inline]
+    fn read<T>(lock: &Self::RwLock<T>) -> Self::ReadGuard<'_, T> {
+        lock.read()
+    }
+
+    #[inline]
+    fn write<T>(lock: &Self::RwLock<T>) -> Self::WriteGuard<'_, T> {
+        lock.write()
+    }
+
+    #[inline]
+    fn new_shared<T>(val: T) -> Self::Shared<T> {
+        alloc::sync::Arc::new(val)
+    }
+
+    #[inline]
+    fn downgrade<T>(shared: &Self::Shared<T>) -> Self::Weak<T> {
+        alloc::sync::Arc::downgrade(shared)
+    }
+
+    #[inline]
+    fn upgrade<T>(weak: &Self::Weak<T>) -> Option<Self::Shared<T>> {
+        weak.upgrade()
+    }
+}
+
+/// The multi-threaded [LockFamily], using the locks from [std::sync].
+///
+/// Poisoning is ignored, like in `parking_lot`.
+#[cfg(feature = "std")]
+pub enum StdFamily {}
+#[cfg(feature = "std")]
+impl LockFamily for StdFamily {
+    type Mutex<T> = std::sync::Mutex<T>;
+    type MutexGuard<'a, T: 'a> = std::sync::MutexGuard<'a, T>;
+    type RwLock<T> = std::sync::RwLock<T>;
+    type ReadGuard<'a, T: 'a> = std::sync::RwLockReadGuard<'a, T>;
+    type WriteGuard<'a, T: 'a> = std::sync::RwLockWriteGuard<'a, T>;
+    type Shared<T> = std::sync::Arc<T>;
+    type Weak<T> = std::sync::Weak<T>;
+
+    #[inline]
+    fn new_mutex<T>(val: T) -> Self::Mutex<T> {
+        std::sync::Mutex::new(val)
+    }
+
+    #[inline]
+    fn lock<T>(mutex: &Self::Mutex<T>) -> Self::MutexGuard<'_, T> {
+        mutex
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    #[inline]
+    fn new_rwlock<T>(val: T) -> Self::RwLock<T> {
+        std::sync::RwLock::new(val)
+    }
+
+    #[inline]
+    fn read<T>(lock: &Self::RwLock<T>) -> Self::ReadGuard<'_, T> {
+        lock.read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    #[inline]
+    fn write<T>(lock: &Self::RwLock<T>) -> Self::WriteGuard<'_, T> {
+        lock.write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    #[inline]
+    fn new_shared<T>(val: T) -> Self::Shared<T> {
+        std::sync::Arc::new(val)
+    }
+
+    #[inline]
+    fn downgrade<T>(shared: &Self::Shared<T>) -> Self::Weak<T> {
+        std::sync::Arc::downgrade(shared)
+    }
+
+    #[inline]
+    fn upgrade<T>(weak: &Self::Weak<T>) -> Option<Self::Shared<T>> {
+        weak.upgrade()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CellFamily, LockFamily};
+
+    /// A tree node with a parent pointer, written once for every family.
+    struct Node<F: LockFamily> {
+        parent: Option<F::Weak<Node<F>>>,
+        children: F::RwLock<Vec<F::Shared<Node<F>>>>,
+        value: F::Mutex<i32>,
+    }
+    impl<F: LockFamily> Node<F> {
+        fn new(parent: Option<&F::Shared<Node<F>>>, value: i32) -> F::Shared<Node<F>> {
+            let node = F::new_shared(Node {
+                parent: parent.map(F::downgrade),
+                children: F::new_rwlock(Vec::new()),
+                value: F::new_mutex(value),
+            });
+            if let Some(parent) = parent {
+                F::write(&parent.children).push(node.clone());
+            }
+            node
+        }
+
+        fn sum(&self) -> i32 {
+            let children = F::read(&self.children);
+            *F::lock(&self.value) + children.iter().map(|child| child.sum()).sum::<i32>()
+        }
+    }
+
+    fn check_family<F: LockFamily>() {
+        let root = Node::<F>::new(None, 1);
+        let child = Node::<F>::new(Some(&root), 2);
+        Node::<F>::new(Some(&child), 3);
+        *F::lock(&child.value) += 10;
+        assert_eq!(root.sum(), 16);
+        let parent = F::upgrade(child.parent.as_ref().unwrap()).unwrap();
+        assert_eq!(*F::lock(&parent.value), 1);
+    }
+
+    #[test]
+    fn cell_family() {
+        check_family::<CellFamily>();
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn std_family() {
+        check_family::<super::StdFamily>();
+    }
+
+    #[test]
+    #[cfg(feature = "parking_lot")]
+    fn parking_lot_family() {
+        check_family::<super::ParkingLotFamily>();
+    }
+}
@@ -0,0 +1,92 @@
+//! A global hook that is notified of every borrow conflict.
+//!
+//! This is similar in spirit to `std::panic::set_hook`,
+//! but runs before the conflict panics, with the full [BorrowError] available.
+//! The hook is stored in a static function pointer, so it also works in `no_std` code.
+
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::BorrowError;
+
+/// The type of a conflict hook.
+///
+/// See [set_conflict_hook] for details.
+pub type ConflictHook = fn(&BorrowError);
+
+/// The currently installed hook, or null if there is none.
+///
+/// Function pointers can't be stored in an atomic directly,
+/// so this is type-erased into a data pointer.
+static CONFLICT_HOOK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Install a hook that runs whenever a borrow conflicts with an existing borrow,
+/// replacing any previously installed hook.
+///
+/// The hook runs before the default panic, so it can be used to log the conflict
+/// or record it for later inspection.
+/// It is not called for conflicts reported by the non-panicking `try_*` methods.
+///
+/// The hook is global, and shared between all threads.
+pub fn set_conflict_hook(hook: ConflictHook) {
+    CONFLICT_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+/// Remove the currently installed conflict hook, returning it if there was one.
+///
+/// On targets without atomic compare-and-swap (like `thumbv6m`),
+/// a hook installed by another thread at the same time may be removed without being returned.
+pub fn take_conflict_hook() -> Option<ConflictHook> {
+    #[cfg(target_has_atomic = "ptr")]
+    let hook = CONFLICT_HOOK.swap(ptr::null_mut(), Ordering::AcqRel);
+    // Only plain loads and stores are available
+    #[cfg(not(target_has_atomic = "ptr"))]
+    let hook = {
+        let hook = CONFLICT_HOOK.load(Ordering::Acquire);
+        CONFLICT_HOOK.store(ptr::null_mut(), Ordering::Release);
+        hook
+    };
+    // SAFETY: The only non-null values we ever store are `ConflictHook`s
+    (!hook.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), ConflictHook>(hook) })
+}
+
+/// Run the installed conflict hook, if any.
+#[cold]
+pub(crate) fn run_conflict_hook(error: &BorrowError) {
+    let hook = CONFLICT_HOOK.load(Ordering::Acquire);
+    if !hook.is_null() {
+        // SAFETY: The only non-null values we ever store are `ConflictHook`s
+        let hook = unsafe { core::mem::transmute::<*mut (), ConflictHook>(hook) };
+        hook(error);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{set_conflict_hook, take_conflict_hook};
+    use crate::tracked::CellRwLock;
+    use crate::BorrowError;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static CONFLICTS: AtomicUsize = AtomicUsize::new(0);
+
+    #[test]
+    fn conflict_hook() {
+        fn hook(error: &BorrowError) {
+            if error.requested_location().file() == file!() {
+                CONFLICTS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        set_conflict_hook(hook);
+        let lock = CellRwLock::new(());
+        let _guard = lock.write();
+        assert!(lock.try_read().is_none());
+        assert_eq!(CONFLICTS.load(Ordering::SeqCst), 0);
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _ = lock.read();
+        }));
+        assert!(res.is_err());
+        assert_eq!(CONFLICTS.load(Ordering::SeqCst), 1);
+        assert!(take_conflict_hook().is_some());
+    }
+}
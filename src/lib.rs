@@ -1,22 +1,108 @@
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 #![doc = include_str!("../README.md")]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+/// Implement `Debug` and `Display` for guard types, forwarding to the guarded data.
+///
+/// Guards that borrow their lock are written with their lifetime, like `MutexGuard<'_>`.
+macro_rules! impl_guard_fmt {
+    ($($guard:ident $(<$lt:lifetime>)?),* $(,)?) => {$(
+        impl<T: ?Sized + core::fmt::Debug> core::fmt::Debug for $guard<$($lt,)? T> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                core::fmt::Debug::fmt(&**self, f)
+            }
+        }
+        impl<T: ?Sized + core::fmt::Display> core::fmt::Display for $guard<$($lt,)? T> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                core::fmt::Display::fmt(&**self, f)
+            }
+        }
+    )*};
+}
+
+mod error;
+pub mod ext;
+#[cfg(feature = "alloc")]
+pub mod family;
+pub mod hook;
+pub mod parking_lot_compat;
+pub mod policy;
 pub mod raw;
+#[cfg(feature = "alloc")]
+pub mod rc;
+pub mod refcell;
+mod state;
+#[cfg(feature = "std")]
+pub mod std_compat;
+pub mod tracked;
+pub mod tracking;
+
+pub use error::{BorrowError, BorrowKind, BorrowRequest};
+pub use ext::CellLockExt;
+#[cfg(feature = "alloc")]
+pub use family::{CellFamily, LockFamily};
+pub use hook::{set_conflict_hook, take_conflict_hook};
+pub use refcell::RefCellExt;
+pub use state::{BorrowSnapshot, BorrowStatus};
+pub use tracking::{location_tracking_enabled, set_location_tracking};
 
 /// A single-threaded [lock_api::Mutex] using a [RefCell](core::cell::RefCell) internally.
 ///
 /// A [CellRwLock] is typically more useful,
 /// and has no additional overhead.
+///
+/// Poisoning is tracked with the `std` feature, and can be checked through [CellLockExt].
+/// Unlike [tracked::CellMutex], this is never `RefUnwindSafe`,
+/// because [lock_api] stores the data in an `UnsafeCell`.
 pub type CellMutex<T> = lock_api::Mutex<raw::CellMutex, T>;
 
+/// A single-threaded [lock_api::Mutex] that always uses fair unlocking.
+///
+/// A single-threaded lock never has any other threads waiting for it,
+/// so this is identical to a [CellMutex].
+/// It exists to match `parking_lot::FairMutex`.
+pub type CellFairMutex<T> = lock_api::Mutex<raw::CellMutex, T>;
+
+/// A single-threaded [lock_api::ReentrantMutex] using a [RefCell](core::cell::RefCell) internally.
+///
+/// Unlike a [CellMutex], this can be locked recursively,
+/// matching the behavior of `parking_lot::ReentrantMutex`.
+pub type CellReentrantMutex<T> = lock_api::ReentrantMutex<raw::CellMutex, raw::CellThreadId, T>;
+
 /// A single-threaded [lock_api::RwLock] using a [RefCell](core::cell::RefCell) internally.
 ///
 /// Useful to abstract between single-threaded and multi-threaded code.
+///
+/// Poisoning is tracked with the `std` feature, and can be checked through [CellLockExt].
+/// Unlike [tracked::CellRwLock], this is never `RefUnwindSafe`,
+/// because [lock_api] stores the data in an `UnsafeCell`.
 pub type CellRwLock<T> = lock_api::RwLock<raw::CellRwLock, T>;
 
+/// A [lock_api::RwLock] which is thread-safe only if the `sync` feature is enabled.
+///
+/// With the `sync` feature, this uses `parking_lot::RawRwLock`.
+/// Otherwise, it is a single-threaded [CellRwLock].
+/// This allows the final binary to choose whether to use threads,
+/// without any `cfg` attributes in the code using the lock.
+///
+/// Only the [lock_api] methods are available on both,
+/// so the extra methods of the raw cell locks should be avoided.
+pub type MaybeSyncRwLock<T> = lock_api::RwLock<raw::MaybeSyncRwLock, T>;
+
+/// A [lock_api::Mutex] which is thread-safe only if the `sync` feature is enabled.
+///
+/// See [MaybeSyncRwLock] for details.
+pub type MaybeSyncMutex<T> = lock_api::Mutex<raw::MaybeSyncMutex, T>;
+
 #[cfg(test)]
 mod test {
-    use super::CellRwLock;
+    use super::raw::CellInstant;
+    use super::{CellFairMutex, CellMutex, CellReentrantMutex, CellRwLock};
+    use super::{MaybeSyncMutex, MaybeSyncRwLock};
+    use core::time::Duration;
+    use lock_api::{MutexGuard, RwLockReadGuard, RwLockUpgradableReadGuard, RwLockWriteGuard};
 
     #[test]
     fn basic_rwlock() {
@@ -33,11 +119,17 @@ mod test {
         {
             let guard = lock.read();
             assert_eq!(*guard, vec![7, 18, 19]);
+            #[cfg(not(feature = "strict-recursion"))]
             {
                 let guard = lock.read();
                 assert_eq!(guard.first(), Some(&7));
                 assert_eq!(guard.last(), Some(&19))
             }
+            {
+                let guard = lock.read_recursive();
+                assert_eq!(guard.first(), Some(&7));
+                assert_eq!(guard.last(), Some(&19))
+            }
         }
         {
             let mut guard = lock.write();
@@ -45,4 +137,139 @@ mod test {
         }
         assert_eq!(lock.into_inner(), vec![7, 18, 19, 42]);
     }
+
+    #[test]
+    fn upgradable_read() {
+        let lock = CellRwLock::new(vec![7i32]);
+        {
+            let guard = lock.upgradable_read();
+            assert!(lock.try_upgradable_read().is_none());
+            assert!(lock.try_write().is_none());
+            {
+                let reader = lock.read_recursive();
+                assert_eq!(*reader, vec![7]);
+            }
+            let mut guard = RwLockUpgradableReadGuard::upgrade(guard);
+            guard.push(18);
+            assert!(lock.try_read().is_none());
+        }
+        {
+            let guard = lock.upgradable_read();
+            let _reader = lock.read_recursive();
+            let guard = RwLockUpgradableReadGuard::try_upgrade(guard)
+                .expect_err("other reader is still active");
+            assert_eq!(*guard, vec![7, 18]);
+        }
+        assert!(!lock.is_locked());
+    }
+
+    #[test]
+    #[should_panic(expected = "would deadlock")]
+    fn upgrade_with_readers() {
+        let lock = CellRwLock::new(());
+        let guard = lock.upgradable_read();
+        let _reader = lock.read_recursive();
+        let _guard = RwLockUpgradableReadGuard::upgrade(guard);
+    }
+
+    #[test]
+    fn downgrade() {
+        let lock = CellRwLock::new(vec![7i32]);
+        {
+            let mut guard = lock.write();
+            guard.push(18);
+            let guard = RwLockWriteGuard::downgrade(guard);
+            let other = lock.read_recursive();
+            assert_eq!(*guard, *other);
+            assert!(lock.try_write().is_none());
+        }
+        {
+            let guard = RwLockWriteGuard::downgrade_to_upgradable(lock.write());
+            assert!(lock.try_upgradable_read().is_none());
+            let guard = RwLockUpgradableReadGuard::downgrade(guard);
+            let other = lock.upgradable_read();
+            assert_eq!(*guard, *other);
+        }
+        assert!(!lock.is_locked());
+    }
+
+    #[test]
+    fn reentrant_mutex() {
+        let mutex = CellReentrantMutex::new(core::cell::Cell::new(7i32));
+        {
+            let outer = mutex.lock();
+            let inner = mutex.lock();
+            inner.set(18);
+            assert_eq!(outer.get(), 18);
+            assert!(mutex.is_owned_by_current_thread());
+        }
+        assert!(!mutex.is_locked());
+        assert_eq!(mutex.into_inner().get(), 18);
+    }
+
+    #[test]
+    fn timed_locks() {
+        let lock = CellRwLock::new(7i32);
+        {
+            let _guard = lock.try_read_for(Duration::from_secs(1)).unwrap();
+            assert!(lock.try_read_recursive_for(Duration::ZERO).is_some());
+            assert!(lock.try_upgradable_read_for(Duration::ZERO).is_some());
+            let deadline = CellInstant::now() + Duration::from_secs(60);
+            assert!(lock.try_write_until(deadline).is_none());
+        }
+        assert!(lock.try_write_for(Duration::from_secs(1)).is_some());
+        let mutex = CellMutex::new(7i32);
+        let _guard = mutex.try_lock_until(CellInstant::now()).unwrap();
+        assert!(mutex.try_lock_for(Duration::from_secs(60)).is_none());
+    }
+
+    #[test]
+    fn fair_unlock() {
+        let mutex = CellFairMutex::new(7i32);
+        {
+            let mut guard = mutex.lock();
+            MutexGuard::bump(&mut guard);
+            *guard = 18;
+            MutexGuard::unlock_fair(guard);
+        }
+        assert!(!mutex.is_locked());
+        let lock = CellRwLock::new(7i32);
+        {
+            let mut guard = lock.read();
+            RwLockReadGuard::bump(&mut guard);
+            RwLockReadGuard::unlock_fair(guard);
+            let mut guard = lock.write();
+            RwLockWriteGuard::bump(&mut guard);
+            RwLockWriteGuard::unlock_fair(guard);
+            let mut guard = lock.upgradable_read();
+            RwLockUpgradableReadGuard::bump(&mut guard);
+            RwLockUpgradableReadGuard::unlock_fair(guard);
+        }
+        assert!(!lock.is_locked());
+    }
+
+    #[test]
+    #[cfg(feature = "strict-recursion")]
+    #[should_panic(expected = "would deadlock")]
+    fn strict_recursion() {
+        let lock = CellRwLock::new(());
+        let _first = lock.read();
+        let _recursive = lock.read_recursive();
+        let _second = lock.read();
+    }
+
+    #[test]
+    fn maybe_sync() {
+        let lock = MaybeSyncRwLock::new(vec![7i32]);
+        lock.write().push(8);
+        assert_eq!(*lock.read(), [7, 8]);
+        let mutex = MaybeSyncMutex::new(7i32);
+        *mutex.lock() += 1;
+        assert_eq!(mutex.into_inner(), 8);
+        #[cfg(feature = "sync")]
+        {
+            fn assert_sync<T: Sync>(_: &T) {}
+            assert_sync(&lock);
+        }
+    }
 }
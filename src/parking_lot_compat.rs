@@ -0,0 +1,433 @@
+//! Single-threaded replacements for the types in the [`parking_lot`](https://docs.rs/parking_lot) crate,
+//! with exactly the same names and methods.
+//!
+//! This allows switching between the two with a single import:
+//! ```
+//! use refcell_lock_api::parking_lot_compat as parking_lot;
+//!
+//! let lock = parking_lot::RwLock::new(7);
+//! assert_eq!(*lock.read(), 7);
+//! ```
+//!
+//! The locks are aliases for the [lock_api] types, exactly like in `parking_lot` itself.
+//! A conflicting borrow panics instead of deadlocking.
+//!
+//! No other thread could ever notify a [Condvar] or finish running a [Once],
+//! so anything that would wait for that panics instead (or times out immediately).
+
+use core::cell::Cell;
+use core::fmt::{self, Debug, Formatter};
+use core::panic::RefUnwindSafe;
+use core::time::Duration;
+
+use crate::raw;
+
+/// A single-threaded [`parking_lot::RawMutex`](https://docs.rs/parking_lot/latest/parking_lot/struct.RawMutex.html).
+pub type RawMutex = raw::CellMutex;
+/// A single-threaded [`parking_lot::RawFairMutex`](https://docs.rs/parking_lot/latest/parking_lot/struct.RawFairMutex.html).
+///
+/// There are never any other threads waiting for the lock,
+/// so this is identical to a [RawMutex].
+pub type RawFairMutex = raw::CellMutex;
+/// A single-threaded [`parking_lot::RawRwLock`](https://docs.rs/parking_lot/latest/parking_lot/struct.RawRwLock.html).
+pub type RawRwLock = raw::CellRwLock;
+/// A single-threaded [`parking_lot::RawThreadId`](https://docs.rs/parking_lot/latest/parking_lot/struct.RawThreadId.html).
+pub type RawThreadId = raw::CellThreadId;
+
+/// A single-threaded mutex.
+pub type Mutex<T> = lock_api::Mutex<RawMutex, T>;
+/// A guard for a locked [Mutex].
+pub type MutexGuard<'a, T> = lock_api::MutexGuard<'a, RawMutex, T>;
+/// A guard for a component of a locked [Mutex].
+pub type MappedMutexGuard<'a, T> = lock_api::MappedMutexGuard<'a, RawMutex, T>;
+
+/// A single-threaded mutex that always uses fair unlocking.
+pub type FairMutex<T> = lock_api::Mutex<RawFairMutex, T>;
+/// A guard for a locked [FairMutex].
+pub type FairMutexGuard<'a, T> = lock_api::MutexGuard<'a, RawFairMutex, T>;
+/// A guard for a component of a locked [FairMutex].
+pub type MappedFairMutexGuard<'a, T> = lock_api::MappedMutexGuard<'a, RawFairMutex, T>;
+
+/// A single-threaded mutex which can be locked recursively.
+pub type ReentrantMutex<T> = lock_api::ReentrantMutex<RawMutex, RawThreadId, T>;
+/// A guard for a locked [ReentrantMutex].
+pub type ReentrantMutexGuard<'a, T> = lock_api::ReentrantMutexGuard<'a, RawMutex, RawThreadId, T>;
+/// A guard for a component of a locked [ReentrantMutex].
+pub type MappedReentrantMutexGuard<'a, T> =
+    lock_api::MappedReentrantMutexGuard<'a, RawMutex, RawThreadId, T>;
+
+/// A single-threaded reader-writer lock.
+pub type RwLock<T> = lock_api::RwLock<RawRwLock, T>;
+/// A guard for a shared borrow of a [RwLock].
+pub type RwLockReadGuard<'a, T> = lock_api::RwLockReadGuard<'a, RawRwLock, T>;
+/// A guard for an exclusive borrow of a [RwLock].
+pub type RwLockWriteGuard<'a, T> = lock_api::RwLockWriteGuard<'a, RawRwLock, T>;
+/// A guard for an upgradable borrow of a [RwLock].
+pub type RwLockUpgradableReadGuard<'a, T> = lock_api::RwLockUpgradableReadGuard<'a, RawRwLock, T>;
+/// A guard for a component of a shared borrow of a [RwLock].
+pub type MappedRwLockReadGuard<'a, T> = lock_api::MappedRwLockReadGuard<'a, RawRwLock, T>;
+/// A guard for a component of an exclusive borrow of a [RwLock].
+pub type MappedRwLockWriteGuard<'a, T> = lock_api::MappedRwLockWriteGuard<'a, RawRwLock, T>;
+
+/// Create a new mutex in a `const` context.
+#[inline]
+pub const fn const_mutex<T>(val: T) -> Mutex<T> {
+    Mutex::const_new(<RawMutex as lock_api::RawMutex>::INIT, val)
+}
+
+/// Create a new fair mutex in a `const` context.
+#[inline]
+pub const fn const_fair_mutex<T>(val: T) -> FairMutex<T> {
+    FairMutex::const_new(<RawFairMutex as lock_api::RawMutex>::INIT, val)
+}
+
+/// Create a new reentrant mutex in a `const` context.
+#[inline]
+pub const fn const_reentrant_mutex<T>(val: T) -> ReentrantMutex<T> {
+    ReentrantMutex::const_new(
+        <RawMutex as lock_api::RawMutex>::INIT,
+        <RawThreadId as lock_api::GetThreadId>::INIT,
+        val,
+    )
+}
+
+/// Create a new reader-writer lock in a `const` context.
+#[inline]
+pub const fn const_rwlock<T>(val: T) -> RwLock<T> {
+    RwLock::const_new(<RawRwLock as lock_api::RawRwLock>::INIT, val)
+}
+
+/// The state of a [Once].
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum OnceState {
+    /// The initialization closure has not been run yet.
+    New,
+    /// The initialization closure panicked.
+    Poisoned,
+    /// The initialization closure is currently running.
+    InProgress,
+    /// The initialization closure completed successfully.
+    Done,
+}
+impl OnceState {
+    /// Check if the initialization closure panicked.
+    #[inline]
+    pub fn poisoned(self) -> bool {
+        matches!(self, OnceState::Poisoned)
+    }
+
+    /// Check if the initialization closure completed successfully.
+    #[inline]
+    pub fn done(self) -> bool {
+        matches!(self, OnceState::Done)
+    }
+}
+
+/// A single-threaded one-time initialization primitive.
+///
+/// ## Panics
+/// Calling [Once::call_once] from inside the initialization closure panics,
+/// because it would deadlock with a real `Once`.
+pub struct Once(Cell<OnceState>);
+impl Once {
+    /// Create a new `Once`.
+    #[inline]
+    pub const fn new() -> Once {
+        Once(Cell::new(OnceState::New))
+    }
+
+    /// The current state of the `Once`.
+    #[inline]
+    pub fn state(&self) -> OnceState {
+        self.0.get()
+    }
+
+    /// Run the initialization closure, if it hasn't already been run.
+    ///
+    /// ## Panics
+    /// If a previous initialization closure panicked, poisoning the `Once`.
+    #[inline]
+    #[track_caller]
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        if self.state().done() {
+            return;
+        }
+        if self.state().poisoned() {
+            panic!("Once instance has previously been poisoned");
+        }
+        self.run(|_| f());
+    }
+
+    /// Run the initialization closure, if it hasn't already been run,
+    /// even if a previous initialization closure panicked.
+    #[inline]
+    #[track_caller]
+    pub fn call_once_force<F: FnOnce(OnceState)>(&self, f: F) {
+        if self.state().done() {
+            return;
+        }
+        self.run(f);
+    }
+
+    #[cold]
+    #[track_caller]
+    fn run<F: FnOnce(OnceState)>(&self, f: F) {
+        /// Poisons the `Once` if the closure panics.
+        struct PoisonOnUnwind<'a>(&'a Once);
+        impl Drop for PoisonOnUnwind<'_> {
+            fn drop(&mut self) {
+                self.0 .0.set(OnceState::Poisoned);
+            }
+        }
+        let state = self.state();
+        if state == OnceState::InProgress {
+            panic!("Once is already being initialized, waiting for it would deadlock");
+        }
+        self.0.set(OnceState::InProgress);
+        let guard = PoisonOnUnwind(self);
+        f(state);
+        core::mem::forget(guard);
+        self.0.set(OnceState::Done);
+    }
+}
+/// A panicking initialization closure poisons the `Once`, like in `parking_lot`.
+impl RefUnwindSafe for Once {}
+impl Default for Once {
+    #[inline]
+    fn default() -> Once {
+        Once::new()
+    }
+}
+impl Debug for Once {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Once")
+            .field("state", &self.state())
+            .finish()
+    }
+}
+
+/// The result of a timed wait on a [Condvar].
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct WaitTimeoutResult(bool);
+impl WaitTimeoutResult {
+    /// Check if the wait timed out.
+    #[inline]
+    pub fn timed_out(self) -> bool {
+        self.0
+    }
+}
+
+/// A single-threaded condition variable.
+///
+/// No other thread could ever notify a single-threaded condition variable,
+/// so waiting without a timeout panics instead of deadlocking,
+/// and waiting with a timeout times out immediately.
+#[derive(Default)]
+pub struct Condvar(());
+impl Condvar {
+    /// Create a new condition variable.
+    #[inline]
+    pub const fn new() -> Condvar {
+        Condvar(())
+    }
+
+    /// Wake up one thread blocked on this condition variable.
+    ///
+    /// Always returns `false`, because no thread could be waiting.
+    #[inline]
+    pub fn notify_one(&self) -> bool {
+        false
+    }
+
+    /// Wake up all threads blocked on this condition variable.
+    ///
+    /// Always returns `0`, because no thread could be waiting.
+    #[inline]
+    pub fn notify_all(&self) -> usize {
+        0
+    }
+
+    /// Block until this condition variable is notified.
+    ///
+    /// ## Panics
+    /// Always, because this would deadlock.
+    #[track_caller]
+    pub fn wait<T: ?Sized>(&self, _mutex_guard: &mut MutexGuard<'_, T>) {
+        wait_would_deadlock()
+    }
+
+    /// Block until this condition variable is notified, or the timeout is reached.
+    ///
+    /// Always times out immediately.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn wait_until<T: ?Sized>(
+        &self,
+        _mutex_guard: &mut MutexGuard<'_, T>,
+        _timeout: std::time::Instant,
+    ) -> WaitTimeoutResult {
+        WaitTimeoutResult(true)
+    }
+
+    /// Block until this condition variable is notified, or the timeout has elapsed.
+    ///
+    /// Always times out immediately.
+    #[inline]
+    pub fn wait_for<T: ?Sized>(
+        &self,
+        _mutex_guard: &mut MutexGuard<'_, T>,
+        _timeout: Duration,
+    ) -> WaitTimeoutResult {
+        WaitTimeoutResult(true)
+    }
+
+    /// Block as long as the condition is true.
+    ///
+    /// ## Panics
+    /// If the condition is true, because this would deadlock.
+    #[track_caller]
+    pub fn wait_while<T, F>(&self, mutex_guard: &mut MutexGuard<'_, T>, mut condition: F)
+    where
+        T: ?Sized,
+        F: FnMut(&mut T) -> bool,
+    {
+        if condition(mutex_guard) {
+            wait_would_deadlock()
+        }
+    }
+
+    /// Block as long as the condition is true, or until the timeout is reached.
+    ///
+    /// Times out immediately if the condition is true.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn wait_while_until<T, F>(
+        &self,
+        mutex_guard: &mut MutexGuard<'_, T>,
+        mut condition: F,
+        _timeout: std::time::Instant,
+    ) -> WaitTimeoutResult
+    where
+        T: ?Sized,
+        F: FnMut(&mut T) -> bool,
+    {
+        WaitTimeoutResult(condition(mutex_guard))
+    }
+
+    /// Block as long as the condition is true, or until the timeout has elapsed.
+    ///
+    /// Times out immediately if the condition is true.
+    #[inline]
+    pub fn wait_while_for<T, F>(
+        &self,
+        mutex_guard: &mut MutexGuard<'_, T>,
+        mut condition: F,
+        _timeout: Duration,
+    ) -> WaitTimeoutResult
+    where
+        T: ?Sized,
+        F: FnMut(&mut T) -> bool,
+    {
+        WaitTimeoutResult(condition(mutex_guard))
+    }
+}
+impl Debug for Condvar {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.pad("Condvar { .. }")
+    }
+}
+
+#[cold]
+#[track_caller]
+fn wait_would_deadlock() -> ! {
+    panic!("Unable to wait on a Condvar: No other thread could notify it, so this would deadlock")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::time::Duration;
+
+    #[test]
+    fn aliases() {
+        let mutex = const_mutex(7i32);
+        *mutex.lock() += 1;
+        assert_eq!(*mutex.lock(), 8);
+        let lock = RwLock::new(vec![7i32]);
+        {
+            let guard = lock.upgradable_read();
+            let mut guard = RwLockUpgradableReadGuard::upgrade(guard);
+            guard.push(8);
+            let guard = RwLockWriteGuard::downgrade(guard);
+            let first = RwLockReadGuard::map(guard, |vec| &vec[0]);
+            assert_eq!(*first, 7);
+        }
+        let mutex = ReentrantMutex::new(());
+        let _first = mutex.lock();
+        let _second = mutex.lock();
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn timed_std_instant() {
+        let deadline = std::time::Instant::now() + Duration::from_secs(60);
+        let mutex = Mutex::new(7i32);
+        {
+            let _guard = mutex.try_lock_until(deadline).unwrap();
+            assert!(mutex.try_lock_until(deadline).is_none());
+        }
+        let lock = RwLock::new(7i32);
+        let _reader = lock.try_read_until(deadline).unwrap();
+        assert!(lock.try_write_until(std::time::Instant::now()).is_none());
+    }
+
+    #[test]
+    fn once() {
+        let once = Once::new();
+        let mut calls = 0;
+        once.call_once(|| calls += 1);
+        once.call_once(|| calls += 1);
+        assert_eq!(calls, 1);
+        assert!(once.state().done());
+
+        let once = Once::new();
+        let res = std::panic::catch_unwind(|| once.call_once(|| panic!("initialization")));
+        assert!(res.is_err());
+        assert!(once.state().poisoned());
+        once.call_once_force(|state| assert!(state.poisoned()));
+        assert_eq!(once.state(), OnceState::Done);
+    }
+
+    #[test]
+    #[should_panic(expected = "would deadlock")]
+    fn recursive_once() {
+        let once = Once::new();
+        once.call_once(|| once.call_once(|| {}));
+    }
+
+    #[test]
+    fn condvar() {
+        let condvar = Condvar::new();
+        let mutex = Mutex::new(false);
+        let mut guard = mutex.lock();
+        assert!(!condvar.notify_one());
+        assert_eq!(condvar.notify_all(), 0);
+        assert!(condvar
+            .wait_for(&mut guard, Duration::from_secs(1))
+            .timed_out());
+        *guard = true;
+        condvar.wait_while(&mut guard, |ready| !*ready);
+        let res = condvar.wait_while_for(&mut guard, |ready| !*ready, Duration::from_secs(1));
+        assert!(!res.timed_out());
+        let res = condvar.wait_while_for(&mut guard, |ready| *ready, Duration::from_secs(1));
+        assert!(res.timed_out());
+    }
+
+    #[test]
+    #[should_panic(expected = "would deadlock")]
+    fn condvar_wait() {
+        let condvar = Condvar::new();
+        let mutex = Mutex::new(());
+        condvar.wait(&mut mutex.lock());
+    }
+}
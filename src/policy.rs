@@ -0,0 +1,100 @@
+//! Policies that decide what happens when a borrow conflicts with an existing borrow.
+//!
+//! The raw locks are generic over a [ConflictPolicy],
+//! which defaults to [PanicPolicy].
+
+use crate::BorrowError;
+
+/// Decides what the blocking lock methods do when a borrow conflicts with an existing borrow.
+///
+/// A real lock would block forever in this situation,
+/// so the policy must not return.
+///
+/// The [conflict hook](crate::set_conflict_hook) is run before the policy is invoked.
+/// Conflicts reported by the non-blocking `try_*` methods never invoke the policy.
+pub trait ConflictPolicy {
+    /// Handle a conflicting borrow.
+    fn on_conflict(error: BorrowError) -> !;
+}
+
+/// Panic with the [BorrowError] as the message.
+///
+/// This is the default policy, matching the behavior of a [RefCell](core::cell::RefCell).
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PanicPolicy;
+impl ConflictPolicy for PanicPolicy {
+    #[cold]
+    #[track_caller]
+    fn on_conflict(error: BorrowError) -> ! {
+        error.panic()
+    }
+}
+
+/// Abort the process after printing the [BorrowError].
+///
+/// With the `std` feature, the message is printed to standard error
+/// before calling `std::process::abort`.
+/// Without it, the message is reported by panicking,
+/// and a second panic during unwinding forces an abort.
+/// This works regardless of the `panic` strategy.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct AbortPolicy;
+impl ConflictPolicy for AbortPolicy {
+    #[cold]
+    #[track_caller]
+    fn on_conflict(error: BorrowError) -> ! {
+        #[cfg(feature = "std")]
+        {
+            std::eprintln!("{error}");
+            std::process::abort()
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            struct AbortOnUnwind;
+            impl Drop for AbortOnUnwind {
+                fn drop(&mut self) {
+                    panic!("Aborting due to conflicting borrow");
+                }
+            }
+            let _guard = AbortOnUnwind;
+            error.panic()
+        }
+    }
+}
+
+/// Log the [BorrowError] using the [log] crate, then panic.
+///
+/// Useful if panic messages are not captured, but logs are.
+#[cfg(feature = "log")]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LogPanicPolicy;
+#[cfg(feature = "log")]
+impl ConflictPolicy for LogPanicPolicy {
+    #[cold]
+    #[track_caller]
+    fn on_conflict(error: BorrowError) -> ! {
+        log::error!("{error}");
+        error.panic()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ConflictPolicy;
+    use crate::{raw, BorrowError};
+
+    struct CustomPolicy;
+    impl ConflictPolicy for CustomPolicy {
+        fn on_conflict(error: BorrowError) -> ! {
+            panic!("Custom policy: {error}")
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Custom policy")]
+    fn custom_policy() {
+        let lock = lock_api::RwLock::<raw::CellRwLock<CustomPolicy>, _>::new(7i32);
+        let _guard = lock.write();
+        let _other = lock.read();
+    }
+}
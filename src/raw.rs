@@ -11,12 +11,134 @@
 //! <https://github.com/rust-lang/rust/blob/714b29a17ff5/library/core/src/cell.rs>
 
 use core::cell::Cell;
-use core::fmt::{Display, Formatter};
+use core::fmt::{Debug, Formatter};
+use core::marker::PhantomData;
+use core::num::NonZeroUsize;
+#[cfg(not(feature = "std"))]
+use core::ops::Add;
 use core::panic::Location;
-use lock_api::{GuardNoSend, RawMutex, RawRwLock, RawRwLockRecursive};
+#[cfg(feature = "std")]
+use core::panic::RefUnwindSafe;
+use core::time::Duration;
+use lock_api::{
+    GetThreadId, GuardNoSend, RawMutex, RawMutexFair, RawMutexTimed, RawRwLock, RawRwLockDowngrade,
+    RawRwLockFair, RawRwLockRecursive, RawRwLockRecursiveTimed, RawRwLockTimed, RawRwLockUpgrade,
+    RawRwLockUpgradeDowngrade, RawRwLockUpgradeFair, RawRwLockUpgradeTimed,
+};
 
-pub struct CellMutex(CellRwLock);
-unsafe impl RawMutex for CellMutex {
+use crate::error::{BorrowError, BorrowRequest};
+use crate::hook::run_conflict_hook;
+use crate::policy::{ConflictPolicy, PanicPolicy};
+use crate::state::{BorrowSnapshot, BorrowStatus, MAX_TRACKED_LOCATIONS};
+#[cfg(all(debug_location, feature = "std"))]
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    boxed::Box,
+    sync::Arc,
+    vec::Vec,
+};
+
+/// A single-threaded implementation of [lock_api::RawMutex],
+/// implemented using a [CellRwLock].
+///
+/// The policy `P` decides what happens when locking fails.
+/// Leaked guards are reported like for a [CellRwLock].
+pub struct CellMutex<P = PanicPolicy>(CellRwLock<P>);
+impl<P: ConflictPolicy> CellMutex<P> {
+    /// Take a snapshot of the current borrow state of the mutex.
+    ///
+    /// See [CellRwLock::borrow_state] for details.
+    #[inline]
+    pub fn borrow_state(&self) -> BorrowSnapshot {
+        self.0.borrow_state()
+    }
+
+    /// Check if the mutex is poisoned.
+    ///
+    /// See [CellRwLock::is_poisoned] for details.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.0.is_poisoned()
+    }
+
+    /// Clear the poisoned state of the mutex.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn clear_poison(&self) {
+        self.0.clear_poison()
+    }
+
+    /// Check that the mutex could be locked, without locking it.
+    ///
+    /// See [CellRwLock::check_shared] for details.
+    #[inline]
+    #[track_caller]
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn check_unlocked(&self) -> Result<(), BorrowError> {
+        if self.0.is_locked() {
+            Err(BorrowError::for_mutex(
+                self.0.borrow_fail(BorrowRequest::Exclusive),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Permanently leak the lock, as in [core::cell::RefMut::leak].
+    ///
+    /// See [CellRwLock::leak_borrow] for details.
+    #[inline]
+    #[track_caller]
+    pub(crate) unsafe fn leak_borrow(&self) {
+        self.0.leak_borrow()
+    }
+
+    /// Unlock the mutex if it was leaked, as in [core::cell::RefCell::undo_leak].
+    ///
+    /// See [CellRwLock::undo_leak] for details.
+    #[inline]
+    pub(crate) unsafe fn undo_leak(&self) {
+        self.0.undo_leak()
+    }
+
+    /// Attempt to lock the mutex,
+    /// returning a [BorrowError] describing the conflict on failure.
+    ///
+    /// This is equivalent to [RawMutex::try_lock].
+    #[inline]
+    #[track_caller]
+    #[allow(clippy::result_large_err)]
+    pub fn try_lock_err(&self) -> Result<(), BorrowError> {
+        self.0
+            .try_borrow_exclusively()
+            .map_err(BorrowError::for_mutex)
+    }
+}
+/// Poisoning makes it safe to observe a mutex after a panic, matching [std::sync::Mutex].
+///
+/// This does not extend to the [crate::CellMutex] alias,
+/// because [lock_api] stores the data in an `UnsafeCell`, which is never `RefUnwindSafe`.
+/// Only the wrappers in [crate::tracked] and [crate::std_compat] are unwind safe.
+#[cfg(feature = "std")]
+impl<P> RefUnwindSafe for CellMutex<P> {}
+impl<P> Drop for CellMutex<P> {
+    #[inline]
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        if self.0.has_active_borrows() {
+            self.0.dropped_while_borrowed(true);
+        }
+    }
+}
+impl<P: ConflictPolicy> Debug for CellMutex<P> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("CellMutex")
+            .field("state", &self.borrow_state())
+            .finish()
+    }
+}
+unsafe impl<P: ConflictPolicy> RawMutex for CellMutex<P> {
     #[allow(clippy::declare_interior_mutable_const)] // Used as workaround for `const fn` in trait
     const INIT: Self = CellMutex(CellRwLock::INIT);
     type GuardMarker = GuardNoSend;
@@ -24,7 +146,10 @@ unsafe impl RawMutex for CellMutex {
     #[inline]
     #[track_caller]
     fn lock(&self) {
-        self.0.lock_exclusive()
+        match self.0.try_borrow_exclusively() {
+            Ok(()) => {}
+            Err(fail) => report_conflict::<P>(fail.for_mutex()),
+        }
     }
 
     #[inline]
@@ -46,6 +171,116 @@ unsafe impl RawMutex for CellMutex {
     }
 }
 
+/// There are never any other threads waiting for the lock,
+/// so a fair unlock is identical to a normal unlock.
+unsafe impl<P: ConflictPolicy> RawMutexFair for CellMutex<P> {
+    #[inline]
+    #[track_caller]
+    unsafe fn unlock_fair(&self) {
+        self.unlock()
+    }
+
+    #[inline]
+    unsafe fn bump(&self) {
+        self.0.bump_exclusive()
+    }
+}
+unsafe impl<P: ConflictPolicy> RawMutexTimed for CellMutex<P> {
+    type Duration = Duration;
+    type Instant = CellInstant;
+
+    #[inline]
+    #[track_caller]
+    fn try_lock_for(&self, timeout: Self::Duration) -> bool {
+        self.0.try_lock_exclusive_for(timeout)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn try_lock_until(&self, timeout: Self::Instant) -> bool {
+        self.0.try_lock_exclusive_until(timeout)
+    }
+}
+
+/// The instant type used for timed locking of the single-threaded locks.
+///
+/// With the `std` feature, this is [std::time::Instant],
+/// so deadlines can be shared with code written for `parking_lot`.
+#[cfg(feature = "std")]
+pub type CellInstant = std::time::Instant;
+/// The instant type used for timed locking of the single-threaded locks.
+///
+/// Waiting for a single-threaded lock can never succeed,
+/// because there is no other thread that could release it.
+/// All timeouts therefore return immediately,
+/// and this type doesn't need to carry any information.
+///
+/// Unlike `std::time::Instant`, this is available in `no_std` code.
+/// It becomes an alias for `std::time::Instant` when the `std` feature is enabled,
+/// so code that must work either way should only use [CellInstant::now] and addition.
+#[cfg(not(feature = "std"))]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct CellInstant(());
+#[cfg(not(feature = "std"))]
+impl CellInstant {
+    /// Return the current instant.
+    #[inline]
+    pub const fn now() -> CellInstant {
+        CellInstant(())
+    }
+}
+#[cfg(not(feature = "std"))]
+impl Add<Duration> for CellInstant {
+    type Output = CellInstant;
+
+    #[inline]
+    fn add(self, _rhs: Duration) -> CellInstant {
+        self
+    }
+}
+
+/// The raw reader-writer lock behind [crate::MaybeSyncRwLock].
+///
+/// This is `parking_lot::RawRwLock` if the `sync` feature is enabled,
+/// and a single-threaded [CellRwLock] otherwise.
+#[cfg(feature = "sync")]
+pub type MaybeSyncRwLock = parking_lot::RawRwLock;
+/// The raw reader-writer lock behind [crate::MaybeSyncRwLock].
+///
+/// This is `parking_lot::RawRwLock` if the `sync` feature is enabled,
+/// and a single-threaded [CellRwLock] otherwise.
+#[cfg(not(feature = "sync"))]
+pub type MaybeSyncRwLock = CellRwLock;
+
+/// The raw mutex behind [crate::MaybeSyncMutex].
+///
+/// This is `parking_lot::RawMutex` if the `sync` feature is enabled,
+/// and a single-threaded [CellMutex] otherwise.
+#[cfg(feature = "sync")]
+pub type MaybeSyncMutex = parking_lot::RawMutex;
+/// The raw mutex behind [crate::MaybeSyncMutex].
+///
+/// This is `parking_lot::RawMutex` if the `sync` feature is enabled,
+/// and a single-threaded [CellMutex] otherwise.
+#[cfg(not(feature = "sync"))]
+pub type MaybeSyncMutex = CellMutex;
+
+/// A single-threaded implementation of [lock_api::GetThreadId].
+///
+/// Always returns the same non-zero thread id,
+/// because a single-threaded lock can never be accessed from another thread.
+///
+/// Used to implement [lock_api::ReentrantMutex] on top of a [CellMutex].
+pub struct CellThreadId(());
+unsafe impl GetThreadId for CellThreadId {
+    const INIT: Self = CellThreadId(());
+
+    #[inline]
+    fn nonzero_thread_id(&self) -> NonZeroUsize {
+        NonZeroUsize::MIN
+    }
+}
+
 /// Maintains a count of the number of borrows active,
 /// and whether they are mutable or immutable.
 ///
@@ -66,14 +301,26 @@ unsafe impl RawMutex for CellMutex {
 ///
 /// ## Differences from stdlib implementation
 /// There are some differences from the implementation used in the stdlib:
-/// 1. Multiple mutable references are forbidden
-/// 2. Uses a newtype instead of a type alias
+/// 1. Uses a newtype instead of a type alias
+/// 2. Tracks whether one of the shared borrows is upgradable
+///
+/// Like the stdlib, multiple exclusive borrows are only created by splitting a guard
+/// into disjoint components, as in [core::cell::RefMut::map_split].
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 struct BorrowFlag {
     count: isize,
+    /// Whether one of the shared borrows is an upgradable borrow.
+    ///
+    /// The upgradable borrow is included in the `count`.
+    /// At most one upgradable borrow can be active at a time,
+    /// alongside any number of ordinary shared borrows.
+    upgradable: bool,
 }
 impl BorrowFlag {
-    pub const UNUSED: BorrowFlag = BorrowFlag { count: 0 };
+    pub const UNUSED: BorrowFlag = BorrowFlag {
+        count: 0,
+        upgradable: false,
+    };
     #[inline]
     pub fn state(self) -> BorrowState {
         // USing comparison chain for speed
@@ -99,53 +346,338 @@ enum BorrowState {
 /// that is implemented using a [RefCell](core::cell::RefCell).
 ///
 /// This can be used to abstract over single-threaded and multi-threaded code.
-#[derive(Debug)]
-pub struct CellRwLock {
+///
+/// The policy `P` decides what happens when a borrow fails.
+/// By default, this panics like a [RefCell](core::cell::RefCell) does.
+///
+/// ## Leaked guards
+/// In debug mode, dropping the lock while a guard is still alive
+/// (for example because of [core::mem::forget]) is reported.
+/// With the `std` feature this panics, unless the thread is already panicking,
+/// and with the `log` feature the leak is logged as an error.
+/// Without either feature there is no way to report it, so the leak goes unnoticed.
+///
+/// Borrows that are leaked on purpose by a `leak` method are never reported.
+pub struct CellRwLock<P = PanicPolicy> {
     borrow_count: Cell<BorrowFlag>,
-    /// Stores the location of the earliest active borrow.
-    ///
-    /// Should be present whenever `self.is_locked()`.
+    /// Stores the locations of all the active borrows.
     ///
     /// Used for giving better panic messages.
     /// This is enabled in debug mode by default,
     /// but can be controlled by feature flags.
     #[cfg(debug_location)]
-    earliest_borrow_location: Cell<Option<&'static Location<'static>>>,
+    borrow_locations: BorrowLocations,
+    /// Whether an exclusive borrow was released while panicking.
+    #[cfg(feature = "std")]
+    poisoned: Cell<bool>,
+    /// Whether the thread was already panicking when the exclusive borrow was acquired.
+    ///
+    /// Like in the standard library, releasing such a borrow does not poison the lock.
+    #[cfg(feature = "std")]
+    panicking_on_acquire: Cell<bool>,
+    /// The number of borrows which were intentionally leaked by a `leak` method.
+    ///
+    /// These are still included in the `borrow_count`.
+    #[cfg(debug_assertions)]
+    leaked: Cell<usize>,
+    /// The location of the first leaked borrow, if it was tracked.
+    #[cfg(debug_assertions)]
+    leak_location: Cell<Option<&'static Location<'static>>>,
+    policy: PhantomData<fn() -> P>,
 }
 
-impl CellRwLock {
-    #[inline]
-    fn earliest_borrow_location(&self) -> Option<&'static Location<'static>> {
+impl<P> CellRwLock<P> {
+    /// Take a snapshot of the current borrow state of the lock.
+    ///
+    /// Includes the locations of the active borrows when `debug-location` is enabled.
+    pub fn borrow_state(&self) -> BorrowSnapshot {
+        let flag = self.borrow_count.get();
+        #[allow(unused_mut)]
+        let mut res = BorrowSnapshot {
+            status: match flag.state() {
+                BorrowState::Unused => BorrowStatus::Unused,
+                BorrowState::SharedBorrow => BorrowStatus::Shared {
+                    readers: flag.count.unsigned_abs(),
+                    upgradable: flag.upgradable,
+                },
+                BorrowState::MutableBorrow => BorrowStatus::Exclusive,
+            },
+            count: flag.count.unsigned_abs(),
+            locations: [None; MAX_TRACKED_LOCATIONS],
+            leaked: 0,
+            leak_location: None,
+        };
         #[cfg(debug_location)]
-        {
-            self.earliest_borrow_location.get()
+        for (dest, src) in res.locations.iter_mut().zip(&self.borrow_locations.entries) {
+            *dest = src.get();
         }
-        #[cfg(not(debug_location))]
+        #[cfg(debug_assertions)]
         {
-            None
+            res.leaked = self.leaked.get();
+            res.leak_location = self.leak_location.get();
+        }
+        res
+    }
+
+    /// Check if the lock has any borrows that were not intentionally leaked.
+    #[cfg(debug_assertions)]
+    #[inline]
+    fn has_active_borrows(&self) -> bool {
+        self.borrow_count.get().count.unsigned_abs() > self.leaked.get()
+    }
+
+    /// Check if the lock is poisoned.
+    ///
+    /// Like a [std::sync::RwLock], the lock becomes poisoned
+    /// when an exclusive borrow is released while the thread is panicking,
+    /// unless the thread was already panicking when the borrow was acquired.
+    /// Shared borrows never poison the lock.
+    ///
+    /// Poisoning is only tracked, never enforced by the lock itself.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.get()
+    }
+
+    /// Clear the poisoned state of the lock.
+    ///
+    /// See [Self::is_poisoned] for details.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn clear_poison(&self) {
+        self.poisoned.set(false);
+    }
+
+    /// Report that the lock is being dropped while it is still borrowed.
+    ///
+    /// This can only happen if a guard was leaked,
+    /// for example with [core::mem::forget] or through an `Rc` cycle.
+    ///
+    /// Panics if the `std` feature is enabled and the thread is not already panicking.
+    /// Otherwise, the leak is logged if the `log` feature is enabled.
+    #[cfg(debug_assertions)]
+    #[cold]
+    #[inline(never)]
+    fn dropped_while_borrowed(&self, is_mutex: bool) {
+        let snapshot = self.borrow_state();
+        // Avoid reporting again when the inner lock of a `CellMutex` is dropped
+        self.borrow_count.set(BorrowFlag::UNUSED);
+        let held = crate::state::HeldBorrows {
+            snapshot: &snapshot,
+            is_mutex,
+        };
+        let name = if is_mutex { "CellMutex" } else { "CellRwLock" };
+        // Panicking in a destructor during unwinding aborts the process,
+        // and without `std` there is no way to check if we are unwinding
+        #[cfg(feature = "std")]
+        if !std::thread::panicking() {
+            panic!("{name} dropped while still {held} (the guard was leaked)")
+        }
+        #[cfg(feature = "log")]
+        log::error!("{name} dropped while still {held} (the guard was leaked)");
+        #[cfg(not(feature = "log"))]
+        let _ = (name, held);
+    }
+}
+/// Poisoning makes it safe to observe a lock after a panic, matching [std::sync::RwLock].
+///
+/// This does not extend to the [crate::CellRwLock] alias,
+/// because [lock_api] stores the data in an `UnsafeCell`, which is never `RefUnwindSafe`.
+/// Only the wrappers in [crate::tracked] and [crate::std_compat] are unwind safe.
+#[cfg(feature = "std")]
+impl<P> RefUnwindSafe for CellRwLock<P> {}
+impl<P> Drop for CellRwLock<P> {
+    #[inline]
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        if self.has_active_borrows() {
+            self.dropped_while_borrowed(false);
+        }
+    }
+}
+impl<P: ConflictPolicy> CellRwLock<P> {
+    /// Attempt to acquire a shared borrow,
+    /// returning a [BorrowError] describing the conflict on failure.
+    ///
+    /// This is equivalent to [RawRwLock::try_lock_shared].
+    #[inline]
+    #[track_caller]
+    #[allow(clippy::result_large_err)]
+    pub fn try_lock_shared_err(&self) -> Result<(), BorrowError> {
+        self.try_borrow_shared()
+    }
+
+    /// Attempt to acquire an upgradable borrow,
+    /// returning a [BorrowError] describing the conflict on failure.
+    ///
+    /// This is equivalent to [RawRwLockUpgrade::try_lock_upgradable].
+    #[inline]
+    #[track_caller]
+    #[allow(clippy::result_large_err)]
+    pub fn try_lock_upgradable_err(&self) -> Result<(), BorrowError> {
+        self.try_borrow_upgradable()
+    }
+
+    /// Attempt to acquire an exclusive borrow,
+    /// returning a [BorrowError] describing the conflict on failure.
+    ///
+    /// This is equivalent to [RawRwLock::try_lock_exclusive].
+    #[inline]
+    #[track_caller]
+    #[allow(clippy::result_large_err)]
+    pub fn try_lock_exclusive_err(&self) -> Result<(), BorrowError> {
+        self.try_borrow_exclusively()
+    }
+
+    #[inline]
+    #[track_caller]
+    fn push_borrow_location(&self) {
+        // Still push an entry when tracking is disabled, so the entries stay in sync with the count
+        #[cfg(debug_location)]
+        if crate::tracking::location_tracking_enabled() {
+            self.borrow_locations.push(Some(Location::caller()));
+        } else {
+            self.borrow_locations.push(None);
         }
     }
 
     #[inline]
+    fn pop_borrow_location(&self) {
+        #[cfg(debug_location)]
+        self.borrow_locations.pop();
+    }
+
+    #[cold]
     #[track_caller]
-    fn try_borrow_exclusively(&self) -> Result<(), BorrowFailError> {
+    fn borrow_fail(&self, request: BorrowRequest) -> BorrowError {
+        let error = BorrowError::new(request, Location::caller(), self.borrow_state());
+        #[cfg(all(debug_location, feature = "std"))]
+        let error = error.with_backtraces(self.borrow_locations.backtraces());
+        error
+    }
+
+    #[inline]
+    #[track_caller]
+    #[allow(clippy::result_large_err)]
+    fn try_borrow_exclusively(&self) -> Result<(), BorrowError> {
         if matches!(self.borrow_count.get().state(), BorrowState::Unused) {
             assert_eq!(self.borrow_count.get().count, 0);
-            self.borrow_count.set(BorrowFlag { count: -1 });
-            #[cfg(debug_location)]
-            self.earliest_borrow_location.set(Location::caller());
+            self.borrow_count.set(BorrowFlag {
+                count: -1,
+                upgradable: false,
+            });
+            self.push_borrow_location();
+            #[cfg(feature = "std")]
+            self.panicking_on_acquire.set(std::thread::panicking());
             Ok(())
         } else {
-            Err(BorrowFailError {
-                is_exclusive: true,
-                existing_location: self.earliest_borrow_location(),
-            })
+            Err(self.borrow_fail(BorrowRequest::Exclusive))
         }
     }
 
+    /// Check that a shared borrow could be acquired, without acquiring it.
+    ///
+    /// Unlike briefly locking and unlocking, this never records a borrow location
+    /// or poisons the lock.
+    #[inline]
+    #[track_caller]
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn check_shared(&self) -> Result<(), BorrowError> {
+        if matches!(self.borrow_count.get().state(), BorrowState::MutableBorrow) {
+            Err(self.borrow_fail(BorrowRequest::Shared))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Permanently leak one of the active borrows, as in [core::cell::Ref::leak].
+    ///
+    /// The borrow is never released, unless [Self::undo_leak] is called.
+    /// In debug mode, the leak is recorded so that later conflicts
+    /// report the lock as permanently borrowed at the caller's location,
+    /// and dropping the lock does not report it as a leaked guard.
+    ///
+    /// ## Safety
+    /// The caller must own the borrow, and never release it.
+    #[inline]
+    #[track_caller]
+    pub(crate) unsafe fn leak_borrow(&self) {
+        debug_assert_ne!(self.borrow_count.get().state(), BorrowState::Unused);
+        self.pop_borrow_location();
+        #[cfg(debug_assertions)]
+        {
+            self.leaked.set(self.leaked.get() + 1);
+            if self.leak_location.get().is_none() && crate::tracking::location_tracking_enabled() {
+                self.leak_location.set(Some(Location::caller()));
+            }
+        }
+    }
+
+    /// Release all leaked borrows, as in [core::cell::RefCell::undo_leak].
+    ///
+    /// ## Safety
+    /// There must not be any active borrows besides the leaked ones,
+    /// and the leaked references must no longer be used.
+    #[inline]
+    pub(crate) unsafe fn undo_leak(&self) {
+        self.borrow_count.set(BorrowFlag::UNUSED);
+        #[cfg(debug_location)]
+        self.borrow_locations.clear();
+        #[cfg(debug_assertions)]
+        {
+            self.leaked.set(0);
+            self.leak_location.set(None);
+        }
+    }
+
+    /// Add another shared borrow, while a shared borrow is already held.
+    ///
+    /// This is used to split a guard into two, like [core::cell::Ref::map_split].
+    ///
+    /// ## Safety
+    /// The lock must already be borrowed shared,
+    /// and the new borrow must be released separately.
+    #[inline]
+    #[track_caller]
+    pub(crate) unsafe fn split_shared(&self) {
+        let flag = self.borrow_count.get();
+        debug_assert_eq!(flag.state(), BorrowState::SharedBorrow);
+        self.borrow_count.set(BorrowFlag {
+            count: flag.count.checked_add(1).expect("Overflow shared borrows"),
+            ..flag
+        });
+        self.push_borrow_location();
+    }
+
+    /// Add another exclusive borrow, while an exclusive borrow is already held.
+    ///
+    /// This is used to split a guard into two disjoint borrows,
+    /// like [core::cell::RefMut::map_split].
+    ///
+    /// ## Safety
+    /// The lock must already be borrowed exclusively,
+    /// the new borrow must be released separately,
+    /// and the two borrows must never access overlapping data.
+    #[inline]
+    #[track_caller]
+    pub(crate) unsafe fn split_exclusive(&self) {
+        let flag = self.borrow_count.get();
+        debug_assert_eq!(flag.state(), BorrowState::MutableBorrow);
+        self.borrow_count.set(BorrowFlag {
+            count: flag
+                .count
+                .checked_sub(1)
+                .expect("Overflow exclusive borrows"),
+            ..flag
+        });
+        self.push_borrow_location();
+    }
+
     #[inline]
     #[track_caller]
-    fn try_borrow_shared(&self) -> Result<(), BorrowFailError> {
+    #[allow(clippy::result_large_err)]
+    fn try_borrow_shared(&self) -> Result<(), BorrowError> {
         if matches!(
             self.borrow_count.get().state(),
             BorrowState::Unused | BorrowState::SharedBorrow
@@ -163,57 +695,190 @@ impl CellRwLock {
                     .count
                     .checked_add(1)
                     .expect("Overflow shared borrows"),
+                ..self.borrow_count.get()
             });
+            self.push_borrow_location();
             Ok(())
         } else {
             debug_assert_eq!(self.borrow_count.get().state(), BorrowState::MutableBorrow);
-            Err(BorrowFailError {
-                is_exclusive: false,
-                existing_location: self.earliest_borrow_location(),
-            })
+            Err(self.borrow_fail(BorrowRequest::Shared))
+        }
+    }
+
+    #[inline]
+    #[track_caller]
+    #[allow(clippy::result_large_err)]
+    fn try_borrow_upgradable(&self) -> Result<(), BorrowError> {
+        let flag = self.borrow_count.get();
+        if matches!(
+            flag.state(),
+            BorrowState::Unused | BorrowState::SharedBorrow
+        ) && !flag.upgradable
+        {
+            self.borrow_count.set(BorrowFlag {
+                // See try_borrow_shared for why we panic on overflow
+                count: flag.count.checked_add(1).expect("Overflow shared borrows"),
+                upgradable: true,
+            });
+            self.push_borrow_location();
+            Ok(())
+        } else {
+            Err(self.borrow_fail(BorrowRequest::Upgradable))
+        }
+    }
+
+    /// Attempt to upgrade the active upgradable borrow into an exclusive borrow.
+    ///
+    /// This fails if any other shared borrows are still active.
+    #[inline]
+    #[track_caller]
+    #[allow(clippy::result_large_err)]
+    fn try_upgrade_borrow(&self) -> Result<(), BorrowError> {
+        let flag = self.borrow_count.get();
+        debug_assert!(flag.upgradable, "No upgradable borrow is active");
+        debug_assert_eq!(flag.state(), BorrowState::SharedBorrow);
+        if flag.count == 1 {
+            self.borrow_count.set(BorrowFlag {
+                count: -1,
+                upgradable: false,
+            });
+            #[cfg(feature = "std")]
+            self.panicking_on_acquire.set(std::thread::panicking());
+            Ok(())
+        } else {
+            Err(self.borrow_fail(BorrowRequest::Upgrade))
         }
     }
 }
-#[derive(Debug)]
-struct BorrowFailError {
-    is_exclusive: bool,
-    existing_location: Option<&'static Location<'static>>,
+
+/// Report a conflicting borrow, running the conflict hook and then the [ConflictPolicy].
+#[cold]
+#[track_caller]
+fn report_conflict<P: ConflictPolicy>(error: BorrowError) -> ! {
+    run_conflict_hook(&error);
+    P::on_conflict(error)
+}
+
+/// A fixed-capacity list of the locations of all active borrows.
+///
+/// The underlying lock API does not identify which borrow is being released,
+/// so locations are removed in reverse order of acquisition.
+/// This is exact for borrows which are released in a nested fashion
+/// (by far the most common case), but may attribute borrows
+/// to the wrong location if they are released out of order.
+#[cfg(debug_location)]
+struct BorrowLocations {
+    entries: [Cell<Option<&'static Location<'static>>>; MAX_TRACKED_LOCATIONS],
+    /// The number of entries that are in use.
+    len: Cell<usize>,
+    /// The number of borrows that didn't fit into the entries.
+    overflow: Cell<usize>,
+    /// The backtraces of the borrows, corresponding to the `entries`.
+    ///
+    /// These are only captured if enabled by the `RUST_BACKTRACE`
+    /// or `RUST_LIB_BACKTRACE` environment variables.
+    #[cfg(feature = "std")]
+    backtraces: [Cell<Option<Arc<Backtrace>>>; MAX_TRACKED_LOCATIONS],
 }
+#[cfg(debug_location)]
+impl BorrowLocations {
+    #[allow(clippy::declare_interior_mutable_const)] // Used to initialize CellRwLock::INIT
+    const EMPTY: BorrowLocations = BorrowLocations {
+        entries: [const { Cell::new(None) }; MAX_TRACKED_LOCATIONS],
+        len: Cell::new(0),
+        overflow: Cell::new(0),
+        #[cfg(feature = "std")]
+        backtraces: [const { Cell::new(None) }; MAX_TRACKED_LOCATIONS],
+    };
+
+    #[inline]
+    fn push(&self, location: Option<&'static Location<'static>>) {
+        let len = self.len.get();
+        match self.entries.get(len) {
+            Some(entry) => {
+                entry.set(location);
+                #[cfg(feature = "std")]
+                if location.is_some() {
+                    let backtrace = Backtrace::capture();
+                    if matches!(backtrace.status(), BacktraceStatus::Captured) {
+                        self.backtraces[len].set(Some(Arc::new(backtrace)));
+                    }
+                }
+                self.len.set(len + 1);
+            }
+            None => self.overflow.set(self.overflow.get() + 1),
+        }
+    }
 
-impl BorrowFailError {
+    #[inline]
+    fn pop(&self) {
+        if self.overflow.get() > 0 {
+            self.overflow.set(self.overflow.get() - 1);
+        } else {
+            let len = self.len.get();
+            debug_assert!(len > 0, "No borrow locations to pop");
+            if let Some(new_len) = len.checked_sub(1) {
+                self.entries[new_len].set(None);
+                #[cfg(feature = "std")]
+                self.backtraces[new_len].set(None);
+                self.len.set(new_len);
+            }
+        }
+    }
+
+    /// Remove all of the entries.
+    #[inline]
+    fn clear(&self) {
+        for entry in &self.entries {
+            entry.set(None);
+        }
+        #[cfg(feature = "std")]
+        for backtrace in &self.backtraces {
+            backtrace.set(None);
+        }
+        self.len.set(0);
+        self.overflow.set(0);
+    }
+
+    /// Clone the captured backtraces of the active borrows,
+    /// along with the corresponding locations.
+    #[cfg(feature = "std")]
     #[cold]
-    #[track_caller]
-    pub fn panic(&self) -> ! {
-        panic!("{self}")
+    fn backtraces(&self) -> Box<[(&'static Location<'static>, Arc<Backtrace>)]> {
+        let mut res = Vec::new();
+        for (entry, backtrace) in self.entries.iter().zip(&self.backtraces) {
+            let captured = backtrace.take();
+            if let (Some(location), Some(captured)) = (entry.get(), &captured) {
+                res.push((location, Arc::clone(captured)));
+            }
+            backtrace.set(captured);
+        }
+        res.into_boxed_slice()
     }
 }
-impl Display for BorrowFailError {
+
+impl<P: ConflictPolicy> Debug for CellRwLock<P> {
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
-        f.write_str("Unable to ")?;
-        if self.is_exclusive {
-            f.write_str("exclusively ")?
-        }
-        f.write_str("borrow")?;
-        if let Some(existing_location) = self.existing_location {
-            write!(
-                f,
-                ": {existing_borrow_kind} borrowed at {existing_location}",
-                existing_borrow_kind = if self.is_exclusive {
-                    "Already"
-                } else {
-                    "Exclusively"
-                }
-            )?;
-        }
-        Ok(())
+        f.debug_struct("CellRwLock")
+            .field("state", &self.borrow_state())
+            .finish()
     }
 }
-unsafe impl RawRwLock for CellRwLock {
+unsafe impl<P: ConflictPolicy> RawRwLock for CellRwLock<P> {
     #[allow(clippy::declare_interior_mutable_const)] // Used as workaround for `const fn` in trait
     const INIT: Self = CellRwLock {
         borrow_count: Cell::new(BorrowFlag::UNUSED),
         #[cfg(debug_location)]
-        earliest_borrow_location: Cell::new(None),
+        borrow_locations: BorrowLocations::EMPTY,
+        #[cfg(feature = "std")]
+        poisoned: Cell::new(false),
+        #[cfg(feature = "std")]
+        panicking_on_acquire: Cell::new(false),
+        #[cfg(debug_assertions)]
+        leaked: Cell::new(0),
+        #[cfg(debug_assertions)]
+        leak_location: Cell::new(None),
+        policy: PhantomData,
     };
     type GuardMarker = GuardNoSend;
 
@@ -221,14 +886,18 @@ unsafe impl RawRwLock for CellRwLock {
     #[inline]
     fn lock_shared(&self) {
         /*
-         * TODO: Do we want to require using read_recursive?
+         * A real lock may block on a recursive shared borrow,
+         * because a writer could be waiting in between.
          *
-         * This may be a stumbling block when switching to a real
-         * lock which blocks on recursive block.
+         * By default we allow it anyways, unless `strict-recursion` is enabled.
          */
+        #[cfg(feature = "strict-recursion")]
+        if matches!(self.borrow_count.get().state(), BorrowState::SharedBorrow) {
+            report_conflict::<P>(self.borrow_fail(BorrowRequest::RecursiveShared))
+        }
         match self.try_borrow_shared() {
             Ok(()) => {}
-            Err(fail) => fail.panic(),
+            Err(fail) => report_conflict::<P>(fail),
         }
     }
 
@@ -245,11 +914,9 @@ unsafe impl RawRwLock for CellRwLock {
         debug_assert!(self.borrow_count.get().count > 0);
         self.borrow_count.set(BorrowFlag {
             count: self.borrow_count.get().count - 1,
+            ..self.borrow_count.get()
         });
-        if !self.is_locked() {
-            #[cfg(debug_location)]
-            self.earliest_borrow_location.set(None);
-        }
+        self.pop_borrow_location();
     }
 
     #[inline]
@@ -257,7 +924,7 @@ unsafe impl RawRwLock for CellRwLock {
     fn lock_exclusive(&self) {
         match self.try_borrow_exclusively() {
             Ok(()) => (),
-            Err(e) => e.panic(),
+            Err(fail) => report_conflict::<P>(fail),
         }
     }
 
@@ -274,10 +941,12 @@ unsafe impl RawRwLock for CellRwLock {
         debug_assert!(self.borrow_count.get().count < 0);
         self.borrow_count.set(BorrowFlag {
             count: self.borrow_count.get().count + 1,
+            upgradable: false,
         });
-        if !self.is_locked() {
-            #[cfg(debug_location)]
-            self.earliest_borrow_location.set(None);
+        self.pop_borrow_location();
+        #[cfg(feature = "std")]
+        if !self.panicking_on_acquire.get() && std::thread::panicking() {
+            self.poisoned.set(true);
         }
     }
 
@@ -294,11 +963,14 @@ unsafe impl RawRwLock for CellRwLock {
         matches!(self.borrow_count.get().state(), BorrowState::MutableBorrow)
     }
 }
-unsafe impl RawRwLockRecursive for CellRwLock {
+unsafe impl<P: ConflictPolicy> RawRwLockRecursive for CellRwLock<P> {
     #[inline]
     #[track_caller]
     fn lock_shared_recursive(&self) {
-        self.lock_shared()
+        match self.try_borrow_shared() {
+            Ok(()) => {}
+            Err(fail) => report_conflict::<P>(fail),
+        }
     }
 
     #[inline]
@@ -307,3 +979,195 @@ unsafe impl RawRwLockRecursive for CellRwLock {
         self.try_lock_shared()
     }
 }
+unsafe impl<P: ConflictPolicy> RawRwLockUpgrade for CellRwLock<P> {
+    #[inline]
+    #[track_caller]
+    fn lock_upgradable(&self) {
+        match self.try_borrow_upgradable() {
+            Ok(()) => {}
+            Err(fail) => report_conflict::<P>(fail),
+        }
+    }
+
+    #[inline]
+    #[track_caller]
+    fn try_lock_upgradable(&self) -> bool {
+        self.try_borrow_upgradable().is_ok()
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn unlock_upgradable(&self) {
+        debug_assert!(self.borrow_count.get().upgradable);
+        self.borrow_count.set(BorrowFlag {
+            upgradable: false,
+            ..self.borrow_count.get()
+        });
+        self.unlock_shared();
+    }
+
+    /// Upgrade the upgradable borrow to an exclusive borrow.
+    ///
+    /// ## Panics
+    /// If any other shared borrows are still active.
+    ///
+    /// A real lock would block waiting for them to be released,
+    /// which would deadlock because they are held by the current thread.
+    #[inline]
+    #[track_caller]
+    unsafe fn upgrade(&self) {
+        match self.try_upgrade_borrow() {
+            Ok(()) => {}
+            Err(fail) => report_conflict::<P>(fail),
+        }
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn try_upgrade(&self) -> bool {
+        self.try_upgrade_borrow().is_ok()
+    }
+}
+/// There are never any other threads waiting for the lock,
+/// so a fair unlock is identical to a normal unlock.
+///
+/// Bumping the lock just checks that it is actually held,
+/// then continues without releasing it.
+unsafe impl<P: ConflictPolicy> RawRwLockFair for CellRwLock<P> {
+    #[inline]
+    #[track_caller]
+    unsafe fn unlock_shared_fair(&self) {
+        self.unlock_shared()
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn unlock_exclusive_fair(&self) {
+        self.unlock_exclusive()
+    }
+
+    #[inline]
+    unsafe fn bump_shared(&self) {
+        debug_assert_eq!(self.borrow_count.get().state(), BorrowState::SharedBorrow);
+    }
+
+    #[inline]
+    unsafe fn bump_exclusive(&self) {
+        debug_assert_eq!(self.borrow_count.get().state(), BorrowState::MutableBorrow);
+    }
+}
+unsafe impl<P: ConflictPolicy> RawRwLockUpgradeFair for CellRwLock<P> {
+    #[inline]
+    #[track_caller]
+    unsafe fn unlock_upgradable_fair(&self) {
+        self.unlock_upgradable()
+    }
+
+    #[inline]
+    unsafe fn bump_upgradable(&self) {
+        debug_assert!(self.borrow_count.get().upgradable);
+    }
+}
+/// Timeouts are ignored, because no other thread could release the lock while waiting.
+///
+/// All methods return the result of the corresponding `try_*` method immediately.
+unsafe impl<P: ConflictPolicy> RawRwLockTimed for CellRwLock<P> {
+    type Duration = Duration;
+    type Instant = CellInstant;
+
+    #[inline]
+    #[track_caller]
+    fn try_lock_shared_for(&self, _timeout: Self::Duration) -> bool {
+        self.try_lock_shared()
+    }
+
+    #[inline]
+    #[track_caller]
+    fn try_lock_shared_until(&self, _timeout: Self::Instant) -> bool {
+        self.try_lock_shared()
+    }
+
+    #[inline]
+    #[track_caller]
+    fn try_lock_exclusive_for(&self, _timeout: Self::Duration) -> bool {
+        self.try_lock_exclusive()
+    }
+
+    #[inline]
+    #[track_caller]
+    fn try_lock_exclusive_until(&self, _timeout: Self::Instant) -> bool {
+        self.try_lock_exclusive()
+    }
+}
+unsafe impl<P: ConflictPolicy> RawRwLockRecursiveTimed for CellRwLock<P> {
+    #[inline]
+    #[track_caller]
+    fn try_lock_shared_recursive_for(&self, _timeout: Self::Duration) -> bool {
+        self.try_lock_shared_recursive()
+    }
+
+    #[inline]
+    #[track_caller]
+    fn try_lock_shared_recursive_until(&self, _timeout: Self::Instant) -> bool {
+        self.try_lock_shared_recursive()
+    }
+}
+unsafe impl<P: ConflictPolicy> RawRwLockUpgradeTimed for CellRwLock<P> {
+    #[inline]
+    #[track_caller]
+    fn try_lock_upgradable_for(&self, _timeout: Self::Duration) -> bool {
+        self.try_lock_upgradable()
+    }
+
+    #[inline]
+    #[track_caller]
+    fn try_lock_upgradable_until(&self, _timeout: Self::Instant) -> bool {
+        self.try_lock_upgradable()
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn try_upgrade_for(&self, _timeout: Self::Duration) -> bool {
+        self.try_upgrade()
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn try_upgrade_until(&self, _timeout: Self::Instant) -> bool {
+        self.try_upgrade()
+    }
+}
+unsafe impl<P: ConflictPolicy> RawRwLockDowngrade for CellRwLock<P> {
+    /// Downgrade the exclusive borrow into a single shared borrow.
+    ///
+    /// This can never fail, because no other borrows can be active.
+    #[inline]
+    unsafe fn downgrade(&self) {
+        debug_assert_eq!(self.borrow_count.get().state(), BorrowState::MutableBorrow);
+        debug_assert_eq!(self.borrow_count.get().count, -1);
+        self.borrow_count.set(BorrowFlag {
+            count: 1,
+            upgradable: false,
+        });
+    }
+}
+unsafe impl<P: ConflictPolicy> RawRwLockUpgradeDowngrade for CellRwLock<P> {
+    #[inline]
+    unsafe fn downgrade_upgradable(&self) {
+        debug_assert!(self.borrow_count.get().upgradable);
+        self.borrow_count.set(BorrowFlag {
+            upgradable: false,
+            ..self.borrow_count.get()
+        });
+    }
+
+    #[inline]
+    unsafe fn downgrade_to_upgradable(&self) {
+        debug_assert_eq!(self.borrow_count.get().state(), BorrowState::MutableBorrow);
+        debug_assert_eq!(self.borrow_count.get().count, -1);
+        self.borrow_count.set(BorrowFlag {
+            count: 1,
+            upgradable: true,
+        });
+    }
+}
@@ -0,0 +1,434 @@
+//! Guards which own an [Rc] of their lock, instead of borrowing it.
+//!
+//! These are the single-threaded equivalent of the `Arc` guards in [lock_api],
+//! like `lock_api::ArcRwLockReadGuard`.
+//! They have a `'static` lifetime, so they can be stored alongside the lock
+//! without any lifetime hacks.
+//!
+//! The guards are acquired through the [RcRwLockExt] and [RcMutexExt] extension traits:
+//! ```
+//! use std::rc::Rc;
+//! use refcell_lock_api::CellRwLock;
+//! use refcell_lock_api::rc::{RcRwLockExt, RcRwLockReadGuard};
+//!
+//! let lock = Rc::new(CellRwLock::new(7));
+//! let guard: RcRwLockReadGuard<i32> = lock.read_rc();
+//! drop(lock);
+//! assert_eq!(*guard, 7);
+//! ```
+
+use alloc::rc::Rc;
+use core::marker::PhantomData;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+
+use lock_api::{GuardNoSend, RawMutex, RawRwLock, RawRwLockDowngrade, RawRwLockRecursive};
+
+use crate::{raw, CellMutex, CellRwLock};
+
+/// Runs the closure when dropped, even if unwinding.
+struct Defer<F: FnMut()>(F);
+impl<F: FnMut()> Drop for Defer<F> {
+    #[inline]
+    fn drop(&mut self) {
+        (self.0)()
+    }
+}
+
+/// Acquire guards for an `Rc<CellRwLock<T>>` which own a clone of the [Rc].
+pub trait RcRwLockExt<T: ?Sized> {
+    /// Acquire a shared borrow of the lock, through an `Rc`.
+    ///
+    /// ## Panics
+    /// If the lock is already borrowed exclusively.
+    #[track_caller]
+    fn read_rc(self: &Rc<Self>) -> RcRwLockReadGuard<T>;
+
+    /// Attempt to acquire a shared borrow of the lock, through an `Rc`.
+    #[track_caller]
+    fn try_read_rc(self: &Rc<Self>) -> Option<RcRwLockReadGuard<T>>;
+
+    /// Acquire a recursive shared borrow of the lock, through an `Rc`.
+    ///
+    /// See [lock_api::RwLock::read_recursive] for details.
+    ///
+    /// ## Panics
+    /// If the lock is already borrowed exclusively.
+    #[track_caller]
+    fn read_recursive_rc(self: &Rc<Self>) -> RcRwLockReadGuard<T>;
+
+    /// Attempt to acquire a recursive shared borrow of the lock, through an `Rc`.
+    #[track_caller]
+    fn try_read_recursive_rc(self: &Rc<Self>) -> Option<RcRwLockReadGuard<T>>;
+
+    /// Acquire an exclusive borrow of the lock, through an `Rc`.
+    ///
+    /// ## Panics
+    /// If the lock is already borrowed.
+    #[track_caller]
+    fn write_rc(self: &Rc<Self>) -> RcRwLockWriteGuard<T>;
+
+    /// Attempt to acquire an exclusive borrow of the lock, through an `Rc`.
+    #[track_caller]
+    fn try_write_rc(self: &Rc<Self>) -> Option<RcRwLockWriteGuard<T>>;
+}
+impl<T: ?Sized> RcRwLockExt<T> for CellRwLock<T> {
+    #[inline]
+    #[track_caller]
+    fn read_rc(self: &Rc<Self>) -> RcRwLockReadGuard<T> {
+        // SAFETY: The guard will release the borrow
+        unsafe { self.raw() }.lock_shared();
+        RcRwLockReadGuard {
+            rwlock: Rc::clone(self),
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    #[track_caller]
+    fn try_read_rc(self: &Rc<Self>) -> Option<RcRwLockReadGuard<T>> {
+        // SAFETY: The guard will release the borrow
+        unsafe { self.raw() }
+            .try_lock_shared()
+            .then(|| RcRwLockReadGuard {
+                rwlock: Rc::clone(self),
+                marker: PhantomData,
+            })
+    }
+
+    #[inline]
+    #[track_caller]
+    fn read_recursive_rc(self: &Rc<Self>) -> RcRwLockReadGuard<T> {
+        // SAFETY: The guard will release the borrow
+        unsafe { self.raw() }.lock_shared_recursive();
+        RcRwLockReadGuard {
+            rwlock: Rc::clone(self),
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    #[track_caller]
+    fn try_read_recursive_rc(self: &Rc<Self>) -> Option<RcRwLockReadGuard<T>> {
+        // SAFETY: The guard will release the borrow
+        unsafe { self.raw() }
+            .try_lock_shared_recursive()
+            .then(|| RcRwLockReadGuard {
+                rwlock: Rc::clone(self),
+                marker: PhantomData,
+            })
+    }
+
+    #[inline]
+    #[track_caller]
+    fn write_rc(self: &Rc<Self>) -> RcRwLockWriteGuard<T> {
+        // SAFETY: The guard will release the borrow
+        unsafe { self.raw() }.lock_exclusive();
+        RcRwLockWriteGuard {
+            rwlock: Rc::clone(self),
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    #[track_caller]
+    fn try_write_rc(self: &Rc<Self>) -> Option<RcRwLockWriteGuard<T>> {
+        // SAFETY: The guard will release the borrow
+        unsafe { self.raw() }
+            .try_lock_exclusive()
+            .then(|| RcRwLockWriteGuard {
+                rwlock: Rc::clone(self),
+                marker: PhantomData,
+            })
+    }
+}
+
+/// Acquire guards for an `Rc<CellMutex<T>>` which own a clone of the [Rc].
+pub trait RcMutexExt<T: ?Sized> {
+    /// Lock the mutex, through an `Rc`.
+    ///
+    /// ## Panics
+    /// If the mutex is already locked.
+    #[track_caller]
+    fn lock_rc(self: &Rc<Self>) -> RcMutexGuard<T>;
+
+    /// Attempt to lock the mutex, through an `Rc`.
+    #[track_caller]
+    fn try_lock_rc(self: &Rc<Self>) -> Option<RcMutexGuard<T>>;
+}
+impl<T: ?Sized> RcMutexExt<T> for CellMutex<T> {
+    #[inline]
+    #[track_caller]
+    fn lock_rc(self: &Rc<Self>) -> RcMutexGuard<T> {
+        // SAFETY: The guard will unlock the mutex
+        unsafe { self.raw() }.lock();
+        RcMutexGuard {
+            mutex: Rc::clone(self),
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    #[track_caller]
+    fn try_lock_rc(self: &Rc<Self>) -> Option<RcMutexGuard<T>> {
+        // SAFETY: The guard will unlock the mutex
+        unsafe { self.raw() }.try_lock().then(|| RcMutexGuard {
+            mutex: Rc::clone(self),
+            marker: PhantomData,
+        })
+    }
+}
+
+/// A shared borrow of a [CellRwLock], which owns an [Rc] of the lock.
+///
+/// This is the equivalent of `lock_api::ArcRwLockReadGuard`.
+#[must_use = "if unused the CellRwLock will immediately unlock"]
+pub struct RcRwLockReadGuard<T: ?Sized> {
+    rwlock: Rc<CellRwLock<T>>,
+    marker: PhantomData<GuardNoSend>,
+}
+impl<T: ?Sized> RcRwLockReadGuard<T> {
+    #[inline]
+    fn raw(&self) -> &raw::CellRwLock {
+        // SAFETY: Only used to release our own borrow
+        unsafe { self.rwlock.raw() }
+    }
+
+    /// Return a reference to the lock, contained in its `Rc`.
+    #[inline]
+    pub fn rwlock(s: &Self) -> &Rc<CellRwLock<T>> {
+        &s.rwlock
+    }
+
+    /// Release the borrow, returning the `Rc` that was held by the guard.
+    #[inline]
+    pub fn into_rc(s: Self) -> Rc<CellRwLock<T>> {
+        let s = ManuallyDrop::new(s);
+        // SAFETY: We hold a shared borrow, and skip our Drop impl
+        unsafe {
+            s.raw().unlock_shared();
+            ptr::read(&s.rwlock)
+        }
+    }
+
+    /// Temporarily release the borrow to execute the given function.
+    #[inline]
+    #[track_caller]
+    pub fn unlocked<F, U>(s: &mut Self, f: F) -> U
+    where
+        F: FnOnce() -> U,
+    {
+        // SAFETY: We hold a shared borrow, which is reacquired afterwards
+        unsafe { s.raw().unlock_shared() };
+        let _relock = Defer(|| s.raw().lock_shared());
+        f()
+    }
+}
+impl<T: ?Sized> Deref for RcRwLockReadGuard<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // SAFETY: We hold a shared borrow
+        unsafe { &*self.rwlock.data_ptr() }
+    }
+}
+impl<T: ?Sized> Drop for RcRwLockReadGuard<T> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: We hold a shared borrow
+        unsafe { self.raw().unlock_shared() }
+    }
+}
+
+/// An exclusive borrow of a [CellRwLock], which owns an [Rc] of the lock.
+///
+/// This is the equivalent of `lock_api::ArcRwLockWriteGuard`.
+#[must_use = "if unused the CellRwLock will immediately unlock"]
+pub struct RcRwLockWriteGuard<T: ?Sized> {
+    rwlock: Rc<CellRwLock<T>>,
+    marker: PhantomData<GuardNoSend>,
+}
+impl<T: ?Sized> RcRwLockWriteGuard<T> {
+    #[inline]
+    fn raw(&self) -> &raw::CellRwLock {
+        // SAFETY: Only used to release our own borrow
+        unsafe { self.rwlock.raw() }
+    }
+
+    /// Return a reference to the lock, contained in its `Rc`.
+    #[inline]
+    pub fn rwlock(s: &Self) -> &Rc<CellRwLock<T>> {
+        &s.rwlock
+    }
+
+    /// Release the borrow, returning the `Rc` that was held by the guard.
+    #[inline]
+    pub fn into_rc(s: Self) -> Rc<CellRwLock<T>> {
+        let s = ManuallyDrop::new(s);
+        // SAFETY: We hold an exclusive borrow, and skip our Drop impl
+        unsafe {
+            s.raw().unlock_exclusive();
+            ptr::read(&s.rwlock)
+        }
+    }
+
+    /// Atomically downgrade to a shared borrow.
+    #[inline]
+    pub fn downgrade(s: Self) -> RcRwLockReadGuard<T> {
+        // SAFETY: We hold an exclusive borrow, which is now shared
+        unsafe { s.raw().downgrade() };
+        let s = ManuallyDrop::new(s);
+        RcRwLockReadGuard {
+            // SAFETY: We skip our Drop impl
+            rwlock: unsafe { ptr::read(&s.rwlock) },
+            marker: PhantomData,
+        }
+    }
+
+    /// Temporarily release the borrow to execute the given function.
+    #[inline]
+    #[track_caller]
+    pub fn unlocked<F, U>(s: &mut Self, f: F) -> U
+    where
+        F: FnOnce() -> U,
+    {
+        // SAFETY: We hold an exclusive borrow, which is reacquired afterwards
+        unsafe { s.raw().unlock_exclusive() };
+        let _relock = Defer(|| s.raw().lock_exclusive());
+        f()
+    }
+}
+impl<T: ?Sized> Deref for RcRwLockWriteGuard<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // SAFETY: We hold an exclusive borrow
+        unsafe { &*self.rwlock.data_ptr() }
+    }
+}
+impl<T: ?Sized> DerefMut for RcRwLockWriteGuard<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: We hold an exclusive borrow
+        unsafe { &mut *self.rwlock.data_ptr() }
+    }
+}
+impl<T: ?Sized> Drop for RcRwLockWriteGuard<T> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: We hold an exclusive borrow
+        unsafe { self.raw().unlock_exclusive() }
+    }
+}
+
+/// A lock on a [CellMutex], which owns an [Rc] of the mutex.
+///
+/// This is the equivalent of `lock_api::ArcMutexGuard`.
+#[must_use = "if unused the CellMutex will immediately unlock"]
+pub struct RcMutexGuard<T: ?Sized> {
+    mutex: Rc<CellMutex<T>>,
+    marker: PhantomData<GuardNoSend>,
+}
+impl<T: ?Sized> RcMutexGuard<T> {
+    #[inline]
+    fn raw(&self) -> &raw::CellMutex {
+        // SAFETY: Only used to release our own lock
+        unsafe { self.mutex.raw() }
+    }
+
+    /// Return a reference to the mutex, contained in its `Rc`.
+    #[inline]
+    pub fn mutex(s: &Self) -> &Rc<CellMutex<T>> {
+        &s.mutex
+    }
+
+    /// Unlock the mutex, returning the `Rc` that was held by the guard.
+    #[inline]
+    pub fn into_rc(s: Self) -> Rc<CellMutex<T>> {
+        let s = ManuallyDrop::new(s);
+        // SAFETY: We hold the lock, and skip our Drop impl
+        unsafe {
+            s.raw().unlock();
+            ptr::read(&s.mutex)
+        }
+    }
+
+    /// Temporarily unlock the mutex to execute the given function.
+    #[inline]
+    #[track_caller]
+    pub fn unlocked<F, U>(s: &mut Self, f: F) -> U
+    where
+        F: FnOnce() -> U,
+    {
+        // SAFETY: We hold the lock, which is reacquired afterwards
+        unsafe { s.raw().unlock() };
+        let _relock = Defer(|| s.raw().lock());
+        f()
+    }
+}
+impl<T: ?Sized> Deref for RcMutexGuard<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // SAFETY: We hold the lock
+        unsafe { &*self.mutex.data_ptr() }
+    }
+}
+impl<T: ?Sized> DerefMut for RcMutexGuard<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: We hold the lock
+        unsafe { &mut *self.mutex.data_ptr() }
+    }
+}
+impl<T: ?Sized> Drop for RcMutexGuard<T> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: We hold the lock
+        unsafe { self.raw().unlock() }
+    }
+}
+
+impl_guard_fmt!(RcRwLockReadGuard, RcRwLockWriteGuard, RcMutexGuard);
+
+#[cfg(test)]
+mod test {
+    use super::{RcMutexExt, RcMutexGuard, RcRwLockExt, RcRwLockReadGuard, RcRwLockWriteGuard};
+    use crate::{CellLockExt, CellMutex, CellRwLock};
+    use alloc::rc::Rc;
+
+    #[test]
+    fn rc_rwlock() {
+        let lock = Rc::new(CellRwLock::new(vec![7i32]));
+        let mut guard = lock.write_rc();
+        assert!(lock.try_read_rc().is_none());
+        guard.push(8);
+        let guard = RcRwLockWriteGuard::downgrade(guard);
+        let other = lock.read_recursive_rc();
+        assert_eq!(*other, [7, 8]);
+        assert!(lock.try_write_rc().is_none());
+        let third = lock.try_read_recursive_rc().unwrap();
+        assert_eq!(lock.borrow_state().count(), 3);
+        drop((other, third));
+        let rwlock = RcRwLockReadGuard::into_rc(guard);
+        assert!(Rc::ptr_eq(&rwlock, &lock));
+        assert!(!lock.is_locked());
+    }
+
+    #[test]
+    fn rc_mutex() {
+        let mutex = Rc::new(CellMutex::new(7i32));
+        let mut guard = mutex.lock_rc();
+        *guard += 1;
+        let value = RcMutexGuard::unlocked(&mut guard, || *mutex.lock());
+        assert_eq!(value, 8);
+        assert!(Rc::ptr_eq(RcMutexGuard::mutex(&guard), &mutex));
+        drop(mutex);
+        let mutex = RcMutexGuard::into_rc(guard);
+        assert_eq!(*mutex.try_lock_rc().unwrap(), 8);
+    }
+}
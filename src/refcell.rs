@@ -0,0 +1,403 @@
+//! The [RefCell](core::cell::RefCell) API, implemented for the cell locks.
+//!
+//! This makes migrating from a `RefCell` to a [CellRwLock](crate::CellRwLock)
+//! close to a search-and-replace of the type name:
+//! ```
+//! use refcell_lock_api::{CellRwLock, RefCellExt};
+//!
+//! let lock = CellRwLock::new(vec![7]);
+//! lock.borrow_mut().push(8);
+//! assert_eq!(lock.replace(vec![]), [7, 8]);
+//! assert!(lock.borrow().is_empty());
+//! ```
+//!
+//! All methods panic on conflicting borrows just like a `RefCell` does,
+//! and use `#[track_caller]` so the reported locations point to the caller.
+
+use core::mem;
+use core::ops::{Deref, DerefMut};
+
+use lock_api::{RawMutex, RawRwLock, RawRwLockRecursive};
+
+use crate::{raw, tracked, BorrowError};
+
+/// The methods of a [RefCell](core::cell::RefCell), for the cell locks.
+///
+/// For a mutex, both [RefCellExt::borrow] and [RefCellExt::borrow_mut]
+/// lock the mutex exclusively.
+pub trait RefCellExt<T: ?Sized> {
+    /// The guard for a shared borrow, like [core::cell::Ref].
+    type Ref<'a>: Deref<Target = T>
+    where
+        Self: 'a;
+    /// The guard for an exclusive borrow, like [core::cell::RefMut].
+    type RefMut<'a>: DerefMut<Target = T>
+    where
+        Self: 'a;
+
+    /// Immutably borrow the wrapped value.
+    ///
+    /// Like a `RefCell`, this allows any number of shared borrows,
+    /// even if the `strict-recursion` feature is enabled.
+    ///
+    /// ## Panics
+    /// If the value is currently mutably borrowed.
+    #[track_caller]
+    fn borrow(&self) -> Self::Ref<'_>;
+
+    /// Immutably borrow the wrapped value,
+    /// returning an error if the value is currently mutably borrowed.
+    #[track_caller]
+    #[allow(clippy::result_large_err)]
+    fn try_borrow(&self) -> Result<Self::Ref<'_>, BorrowError>;
+
+    /// Mutably borrow the wrapped value.
+    ///
+    /// ## Panics
+    /// If the value is currently borrowed.
+    #[track_caller]
+    fn borrow_mut(&self) -> Self::RefMut<'_>;
+
+    /// Mutably borrow the wrapped value,
+    /// returning an error if the value is currently borrowed.
+    #[track_caller]
+    #[allow(clippy::result_large_err)]
+    fn try_borrow_mut(&self) -> Result<Self::RefMut<'_>, BorrowError>;
+
+    /// Immutably borrow the wrapped value without a guard,
+    /// returning an error if the value is currently mutably borrowed.
+    ///
+    /// ## Safety
+    /// Like [RefCell::try_borrow_unguarded](core::cell::RefCell::try_borrow_unguarded),
+    /// the value must not be mutably borrowed while the returned reference is alive.
+    #[track_caller]
+    #[allow(clippy::result_large_err)]
+    unsafe fn try_borrow_unguarded(&self) -> Result<&T, BorrowError>;
+
+    /// Replace the wrapped value with a new one, returning the old value.
+    ///
+    /// ## Panics
+    /// If the value is currently borrowed.
+    #[inline]
+    #[track_caller]
+    fn replace(&self, t: T) -> T
+    where
+        T: Sized,
+    {
+        mem::replace(&mut *self.borrow_mut(), t)
+    }
+
+    /// Replace the wrapped value with a new one computed from `f`,
+    /// returning the old value.
+    ///
+    /// ## Panics
+    /// If the value is currently borrowed.
+    #[inline]
+    #[track_caller]
+    fn replace_with<F: FnOnce(&mut T) -> T>(&self, f: F) -> T
+    where
+        T: Sized,
+    {
+        let mut guard = self.borrow_mut();
+        let replacement = f(&mut guard);
+        mem::replace(&mut *guard, replacement)
+    }
+
+    /// Swap the wrapped value of `self` with the wrapped value of `other`.
+    ///
+    /// ## Panics
+    /// If the value in either lock is currently borrowed,
+    /// or if `self` and `other` are the same lock.
+    #[inline]
+    #[track_caller]
+    fn swap(&self, other: &Self)
+    where
+        T: Sized,
+    {
+        mem::swap(&mut *self.borrow_mut(), &mut *other.borrow_mut())
+    }
+
+    /// Take the wrapped value, leaving `Default::default()` in its place.
+    ///
+    /// ## Panics
+    /// If the value is currently borrowed.
+    #[inline]
+    #[track_caller]
+    fn take(&self) -> T
+    where
+        T: Default,
+    {
+        self.replace(T::default())
+    }
+}
+
+impl<T: ?Sized> RefCellExt<T> for crate::CellRwLock<T> {
+    type Ref<'a>
+        = lock_api::RwLockReadGuard<'a, raw::CellRwLock, T>
+    where
+        T: 'a;
+    type RefMut<'a>
+        = lock_api::RwLockWriteGuard<'a, raw::CellRwLock, T>
+    where
+        T: 'a;
+
+    #[inline]
+    #[track_caller]
+    fn borrow(&self) -> Self::Ref<'_> {
+        // SAFETY: The guard will release the borrow
+        unsafe {
+            self.raw().lock_shared_recursive();
+            self.make_read_guard_unchecked()
+        }
+    }
+
+    #[inline]
+    #[track_caller]
+    fn try_borrow(&self) -> Result<Self::Ref<'_>, BorrowError> {
+        // SAFETY: The guard will release the borrow
+        unsafe {
+            self.raw().try_lock_shared_err()?;
+            Ok(self.make_read_guard_unchecked())
+        }
+    }
+
+    #[inline]
+    #[track_caller]
+    fn borrow_mut(&self) -> Self::RefMut<'_> {
+        // SAFETY: The guard will release the borrow
+        unsafe {
+            self.raw().lock_exclusive();
+            self.make_write_guard_unchecked()
+        }
+    }
+
+    #[inline]
+    #[track_caller]
+    fn try_borrow_mut(&self) -> Result<Self::RefMut<'_>, BorrowError> {
+        // SAFETY: The guard will release the borrow
+        unsafe {
+            self.raw().try_lock_exclusive_err()?;
+            Ok(self.make_write_guard_unchecked())
+        }
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn try_borrow_unguarded(&self) -> Result<&T, BorrowError> {
+        self.raw().check_shared()?;
+        Ok(&*self.data_ptr())
+    }
+}
+
+impl<T: ?Sized> RefCellExt<T> for crate::CellMutex<T> {
+    type Ref<'a>
+        = lock_api::MutexGuard<'a, raw::CellMutex, T>
+    where
+        T: 'a;
+    type RefMut<'a>
+        = lock_api::MutexGuard<'a, raw::CellMutex, T>
+    where
+        T: 'a;
+
+    #[inline]
+    #[track_caller]
+    fn borrow(&self) -> Self::Ref<'_> {
+        self.borrow_mut()
+    }
+
+    #[inline]
+    #[track_caller]
+    fn try_borrow(&self) -> Result<Self::Ref<'_>, BorrowError> {
+        self.try_borrow_mut()
+    }
+
+    #[inline]
+    #[track_caller]
+    fn borrow_mut(&self) -> Self::RefMut<'_> {
+        // SAFETY: The guard will unlock the mutex
+        unsafe {
+            self.raw().lock();
+            self.make_guard_unchecked()
+        }
+    }
+
+    #[inline]
+    #[track_caller]
+    fn try_borrow_mut(&self) -> Result<Self::RefMut<'_>, BorrowError> {
+        // SAFETY: The guard will unlock the mutex
+        unsafe {
+            self.raw().try_lock_err()?;
+            Ok(self.make_guard_unchecked())
+        }
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn try_borrow_unguarded(&self) -> Result<&T, BorrowError> {
+        self.raw().check_unlocked()?;
+        Ok(&*self.data_ptr())
+    }
+}
+
+impl<T: ?Sized> RefCellExt<T> for tracked::CellRwLock<T> {
+    type Ref<'a>
+        = tracked::CellRwLockReadGuard<'a, T>
+    where
+        T: 'a;
+    type RefMut<'a>
+        = tracked::CellRwLockWriteGuard<'a, T>
+    where
+        T: 'a;
+
+    #[inline]
+    #[track_caller]
+    fn borrow(&self) -> Self::Ref<'_> {
+        self.read_recursive()
+    }
+
+    #[inline]
+    #[track_caller]
+    fn try_borrow(&self) -> Result<Self::Ref<'_>, BorrowError> {
+        self.try_read_err()
+    }
+
+    #[inline]
+    #[track_caller]
+    fn borrow_mut(&self) -> Self::RefMut<'_> {
+        self.write()
+    }
+
+    #[inline]
+    #[track_caller]
+    fn try_borrow_mut(&self) -> Result<Self::RefMut<'_>, BorrowError> {
+        self.try_write_err()
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn try_borrow_unguarded(&self) -> Result<&T, BorrowError> {
+        self.as_lock_api().try_borrow_unguarded()
+    }
+}
+
+impl<T: ?Sized> RefCellExt<T> for tracked::CellMutex<T> {
+    type Ref<'a>
+        = tracked::CellMutexGuard<'a, T>
+    where
+        T: 'a;
+    type RefMut<'a>
+        = tracked::CellMutexGuard<'a, T>
+    where
+        T: 'a;
+
+    #[inline]
+    #[track_caller]
+    fn borrow(&self) -> Self::Ref<'_> {
+        self.lock()
+    }
+
+    #[inline]
+    #[track_caller]
+    fn try_borrow(&self) -> Result<Self::Ref<'_>, BorrowError> {
+        self.try_lock_err()
+    }
+
+    #[inline]
+    #[track_caller]
+    fn borrow_mut(&self) -> Self::RefMut<'_> {
+        self.lock()
+    }
+
+    #[inline]
+    #[track_caller]
+    fn try_borrow_mut(&self) -> Result<Self::RefMut<'_>, BorrowError> {
+        self.try_lock_err()
+    }
+
+    #[inline]
+    #[track_caller]
+    unsafe fn try_borrow_unguarded(&self) -> Result<&T, BorrowError> {
+        self.as_lock_api().try_borrow_unguarded()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RefCellExt;
+    use crate::{tracked, BorrowRequest, CellMutex, CellRwLock};
+
+    #[test]
+    fn refcell_rwlock() {
+        let lock = CellRwLock::new(7i32);
+        {
+            let first = lock.borrow();
+            let second = lock.try_borrow().unwrap();
+            let third = lock.borrow();
+            assert_eq!(*first + *second + *third, 21);
+            let err = lock.try_borrow_mut().unwrap_err();
+            assert_eq!(err.request(), BorrowRequest::Exclusive);
+            assert_eq!(unsafe { lock.try_borrow_unguarded() }.ok(), Some(&7));
+        }
+        *lock.borrow_mut() += 1;
+        assert_eq!(lock.replace(10), 8);
+        assert_eq!(lock.replace_with(|old| *old + 1), 10);
+        let other = CellRwLock::new(0);
+        lock.swap(&other);
+        assert_eq!(lock.take(), 0);
+        assert_eq!(other.into_inner(), 11);
+        let _guard = lock.borrow_mut();
+        assert!(unsafe { lock.try_borrow_unguarded() }.is_err());
+    }
+
+    #[test]
+    fn refcell_mutex() {
+        let mutex = CellMutex::new(7i32);
+        {
+            let _guard = mutex.borrow();
+            assert!(mutex.try_borrow().unwrap_err().is_mutex());
+        }
+        assert_eq!(mutex.take(), 7);
+        let mutex = tracked::CellMutex::new(vec![7i32]);
+        mutex.borrow_mut().push(8);
+        assert_eq!(mutex.take(), [7, 8]);
+    }
+
+    #[test]
+    fn refcell_location() {
+        let lock = tracked::CellRwLock::new(7i32);
+        let _guard = lock.borrow();
+        let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| lock.replace(8)));
+        let line = line!() - 1;
+        let payload = payload.unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+        let location = format!("Unable to exclusively borrow at {}:{line}:", file!());
+        assert!(message.contains(&location), "{message}");
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn unguarded_while_panicking() {
+        struct BorrowOnDrop<'a>(&'a CellMutex<i32>);
+        impl Drop for BorrowOnDrop<'_> {
+            fn drop(&mut self) {
+                assert_eq!(unsafe { self.0.try_borrow_unguarded() }.ok(), Some(&7));
+            }
+        }
+        let mutex = CellMutex::new(7i32);
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _borrow = BorrowOnDrop(&mutex);
+            panic!("unwinding");
+        }));
+        assert!(!crate::CellLockExt::is_poisoned(&mutex));
+        let _guard = mutex.lock();
+        let err = unsafe { mutex.try_borrow_unguarded() }.unwrap_err();
+        assert!(err.is_mutex());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unable to exclusively borrow")]
+    fn swap_with_itself() {
+        let lock = CellRwLock::new(7i32);
+        lock.swap(&lock);
+    }
+}
@@ -0,0 +1,180 @@
+//! Introspection of the borrow state of a lock.
+
+use core::fmt::{self, Debug, Display, Formatter};
+use core::panic::Location;
+
+use crate::BorrowKind;
+
+/// The maximum number of borrow locations that are tracked.
+///
+/// Any borrows beyond this are still counted, but their locations are not recorded.
+pub(crate) const MAX_TRACKED_LOCATIONS: usize = 8;
+
+/// Whether a lock is borrowed, and how.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum BorrowStatus {
+    /// The lock is not borrowed.
+    Unused,
+    /// The lock has one or more shared borrows.
+    Shared {
+        /// The number of active shared borrows,
+        /// including the upgradable borrow.
+        readers: usize,
+        /// Whether one of the shared borrows is upgradable.
+        upgradable: bool,
+    },
+    /// The lock is borrowed exclusively.
+    Exclusive,
+}
+
+/// A snapshot of the borrow state of a lock,
+/// returned by [raw::CellRwLock::borrow_state](crate::raw::CellRwLock::borrow_state).
+///
+/// Includes the locations of the active borrows when `debug-location` is enabled.
+#[derive(Copy, Clone)]
+pub struct BorrowSnapshot {
+    pub(crate) status: BorrowStatus,
+    pub(crate) count: usize,
+    /// The locations of the active borrows, in order of acquisition.
+    ///
+    /// Missing if they are not tracked.
+    pub(crate) locations: [Option<&'static Location<'static>>; MAX_TRACKED_LOCATIONS],
+    /// The number of borrows that were leaked, included in the `count`.
+    pub(crate) leaked: usize,
+    /// The location of the first leaked borrow, if it was tracked.
+    pub(crate) leak_location: Option<&'static Location<'static>>,
+}
+impl BorrowSnapshot {
+    /// Whether the lock is borrowed, and how.
+    #[inline]
+    pub fn status(&self) -> BorrowStatus {
+        self.status
+    }
+
+    /// The kind of borrow that currently holds the lock,
+    /// or `None` if the lock is unused.
+    #[inline]
+    pub fn kind(&self) -> Option<BorrowKind> {
+        match self.status {
+            BorrowStatus::Unused => None,
+            BorrowStatus::Shared {
+                upgradable: false, ..
+            } => Some(BorrowKind::Shared),
+            BorrowStatus::Shared {
+                upgradable: true, ..
+            } => Some(BorrowKind::Upgradable),
+            BorrowStatus::Exclusive => Some(BorrowKind::Exclusive),
+        }
+    }
+
+    /// Check if the lock is not borrowed.
+    #[inline]
+    pub fn is_unused(&self) -> bool {
+        matches!(self.status, BorrowStatus::Unused)
+    }
+
+    /// The total number of active borrows.
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// The locations of the borrows that currently hold the lock,
+    /// in the order they were acquired.
+    ///
+    /// This is empty unless the `debug-location` feature is enabled.
+    /// It may also contain fewer locations than [`Self::count`],
+    /// because only a limited number of locations are tracked.
+    #[inline]
+    pub fn locations(&self) -> impl Iterator<Item = &'static Location<'static>> + '_ {
+        self.locations.iter().flatten().copied()
+    }
+
+    /// The number of borrows which were permanently leaked,
+    /// using a `leak` method like [CellRwLockReadGuard::leak](crate::tracked::CellRwLockReadGuard::leak).
+    ///
+    /// These are included in [Self::count], but not in [Self::locations].
+    /// Leaks are only recorded in debug mode, so this is always zero in release mode.
+    #[inline]
+    pub fn leaked(&self) -> usize {
+        self.leaked
+    }
+
+    /// The location where a borrow was first leaked.
+    ///
+    /// This is only recorded in debug mode, when borrow locations are tracked.
+    #[inline]
+    pub fn leak_location(&self) -> Option<&'static Location<'static>> {
+        self.leak_location
+    }
+}
+impl Debug for BorrowSnapshot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut res = f.debug_struct("BorrowSnapshot");
+        res.field("status", &self.status);
+        if self.locations().next().is_some() {
+            res.field("locations", &LocationList(self));
+        }
+        if self.leaked > 0 {
+            res.field("leaked", &self.leaked);
+        }
+        res.finish()
+    }
+}
+struct LocationList<'a>(&'a BorrowSnapshot);
+impl Debug for LocationList<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        for location in self.0.locations() {
+            list.entry(&format_args!("{location}"));
+        }
+        list.finish()
+    }
+}
+
+/// Describes the borrows in a [BorrowSnapshot] for an error message,
+/// as in "exclusively borrowed at src/main.rs:7:13".
+pub(crate) struct HeldBorrows<'a> {
+    pub(crate) snapshot: &'a BorrowSnapshot,
+    /// Use mutex wording instead of talking about borrows.
+    pub(crate) is_mutex: bool,
+}
+impl Display for HeldBorrows<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let count = self.snapshot.count();
+        match (self.is_mutex, self.snapshot.kind()) {
+            (_, None) => f.write_str("unused")?,
+            (true, Some(_)) => f.write_str("locked")?,
+            (false, Some(BorrowKind::Exclusive)) => f.write_str("exclusively borrowed")?,
+            (false, Some(BorrowKind::Shared)) => write!(f, "borrowed by {count} reader(s)")?,
+            (false, Some(BorrowKind::Upgradable)) => {
+                write!(f, "upgradably borrowed, with {count} reader(s) in total")?
+            }
+        }
+        let leaked = self.snapshot.leaked();
+        let mut locations = self.snapshot.locations();
+        if let Some(first_location) = locations.next() {
+            write!(f, " at {first_location}")?;
+            let mut known_locations = 1;
+            for location in locations {
+                write!(f, ", {location}")?;
+                known_locations += 1;
+            }
+            let unknown = count.saturating_sub(known_locations + leaked);
+            if unknown > 0 {
+                write!(f, ", and {unknown} unknown location(s)")?;
+            }
+        }
+        if leaked > 0 {
+            match (leaked, self.snapshot.leak_location()) {
+                (1, Some(location)) => write!(f, " (permanently borrowed at {location})")?,
+                (1, None) => f.write_str(" (permanently borrowed)")?,
+                (_, Some(location)) => {
+                    write!(f, " ({leaked} permanently borrowed, first at {location})")?
+                }
+                (_, None) => write!(f, " ({leaked} permanently borrowed)")?,
+            }
+        }
+        Ok(())
+    }
+}
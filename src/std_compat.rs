@@ -0,0 +1,362 @@
+//! Single-threaded locks with exactly the same API as [std::sync::Mutex] and [std::sync::RwLock].
+//!
+//! This allows code written against the standard library locks
+//! to switch to single-threaded locks by changing a single import.
+//! Poisoning behaves the same as in the standard library.
+//!
+//! The only difference is that a conflicting borrow panics instead of deadlocking.
+//! Borrow locations point to the caller, like in the [crate::tracked] module.
+
+use core::fmt::{self, Debug, Formatter};
+use core::ops::{Deref, DerefMut};
+use std::sync::{LockResult, PoisonError, TryLockError, TryLockResult};
+
+use crate::tracked;
+
+/// Wrap the result of acquiring a lock, based on whether it is poisoned.
+#[inline]
+fn map_poison<G>(poisoned: bool, guard: G) -> LockResult<G> {
+    if poisoned {
+        Err(PoisonError::new(guard))
+    } else {
+        Ok(guard)
+    }
+}
+
+/// A single-threaded mutex with the same API as [std::sync::Mutex].
+pub struct Mutex<T: ?Sized> {
+    inner: tracked::CellMutex<T>,
+}
+impl<T> Mutex<T> {
+    /// Create a new mutex in the unlocked state.
+    #[inline]
+    pub const fn new(t: T) -> Self {
+        Mutex {
+            inner: tracked::CellMutex::new(t),
+        }
+    }
+
+    /// Consume this mutex, returning the underlying data.
+    ///
+    /// ## Errors
+    /// If the mutex is poisoned, the data is returned inside the error.
+    #[inline]
+    pub fn into_inner(self) -> LockResult<T> {
+        let poisoned = self.inner.is_poisoned();
+        map_poison(poisoned, self.inner.into_inner())
+    }
+}
+impl<T: ?Sized> Mutex<T> {
+    /// Lock the mutex.
+    ///
+    /// ## Errors
+    /// If the mutex is poisoned, the guard is returned inside the error.
+    ///
+    /// ## Panics
+    /// If the mutex is already locked.
+    /// A [std::sync::Mutex] would deadlock instead.
+    #[inline]
+    #[track_caller]
+    pub fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+        let guard = MutexGuard {
+            inner: self.inner.lock(),
+        };
+        map_poison(self.inner.is_poisoned(), guard)
+    }
+
+    /// Attempt to lock the mutex,
+    /// failing with [TryLockError::WouldBlock] if it is already locked.
+    #[inline]
+    #[track_caller]
+    pub fn try_lock(&self) -> TryLockResult<MutexGuard<'_, T>> {
+        match self.inner.try_lock() {
+            Some(inner) => Ok(map_poison(self.inner.is_poisoned(), MutexGuard { inner })?),
+            None => Err(TryLockError::WouldBlock),
+        }
+    }
+
+    /// Check if the mutex is poisoned.
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+
+    /// Clear the poisoned state of the mutex.
+    #[inline]
+    pub fn clear_poison(&self) {
+        self.inner.clear_poison()
+    }
+
+    /// Return a mutable reference to the underlying data.
+    ///
+    /// ## Errors
+    /// If the mutex is poisoned, the reference is returned inside the error.
+    #[inline]
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        let poisoned = self.inner.is_poisoned();
+        map_poison(poisoned, self.inner.get_mut())
+    }
+}
+impl<T: Default> Default for Mutex<T> {
+    #[inline]
+    fn default() -> Self {
+        Mutex::new(T::default())
+    }
+}
+impl<T> From<T> for Mutex<T> {
+    #[inline]
+    fn from(t: T) -> Self {
+        Mutex::new(t)
+    }
+}
+impl<T: ?Sized + Debug> Debug for Mutex<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("Mutex");
+        match self.inner.try_lock() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.field("poisoned", &self.is_poisoned());
+        d.finish_non_exhaustive()
+    }
+}
+
+/// A guard for a locked [Mutex], with the same API as [std::sync::MutexGuard].
+#[must_use = "if unused the Mutex will immediately unlock"]
+pub struct MutexGuard<'a, T: ?Sized> {
+    inner: tracked::CellMutexGuard<'a, T>,
+}
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+/// A single-threaded reader-writer lock with the same API as [std::sync::RwLock].
+pub struct RwLock<T: ?Sized> {
+    inner: tracked::CellRwLock<T>,
+}
+impl<T> RwLock<T> {
+    /// Create a new lock in the unlocked state.
+    #[inline]
+    pub const fn new(t: T) -> Self {
+        RwLock {
+            inner: tracked::CellRwLock::new(t),
+        }
+    }
+
+    /// Consume this lock, returning the underlying data.
+    ///
+    /// ## Errors
+    /// If the lock is poisoned, the data is returned inside the error.
+    #[inline]
+    pub fn into_inner(self) -> LockResult<T> {
+        let poisoned = self.inner.is_poisoned();
+        map_poison(poisoned, self.inner.into_inner())
+    }
+}
+impl<T: ?Sized> RwLock<T> {
+    /// Acquire a shared borrow of the lock.
+    ///
+    /// ## Errors
+    /// If the lock is poisoned, the guard is returned inside the error.
+    ///
+    /// ## Panics
+    /// If the lock is already borrowed exclusively.
+    /// A [std::sync::RwLock] would deadlock instead.
+    #[inline]
+    #[track_caller]
+    pub fn read(&self) -> LockResult<RwLockReadGuard<'_, T>> {
+        let guard = RwLockReadGuard {
+            inner: self.inner.read(),
+        };
+        map_poison(self.inner.is_poisoned(), guard)
+    }
+
+    /// Attempt to acquire a shared borrow of the lock,
+    /// failing with [TryLockError::WouldBlock] if it is borrowed exclusively.
+    #[inline]
+    #[track_caller]
+    pub fn try_read(&self) -> TryLockResult<RwLockReadGuard<'_, T>> {
+        match self.inner.try_read() {
+            Some(inner) => Ok(map_poison(
+                self.inner.is_poisoned(),
+                RwLockReadGuard { inner },
+            )?),
+            None => Err(TryLockError::WouldBlock),
+        }
+    }
+
+    /// Acquire an exclusive borrow of the lock.
+    ///
+    /// ## Errors
+    /// If the lock is poisoned, the guard is returned inside the error.
+    ///
+    /// ## Panics
+    /// If the lock is already borrowed.
+    /// A [std::sync::RwLock] would deadlock instead.
+    #[inline]
+    #[track_caller]
+    pub fn write(&self) -> LockResult<RwLockWriteGuard<'_, T>> {
+        let guard = RwLockWriteGuard {
+            inner: self.inner.write(),
+        };
+        map_poison(self.inner.is_poisoned(), guard)
+    }
+
+    /// Attempt to acquire an exclusive borrow of the lock,
+    /// failing with [TryLockError::WouldBlock] if it is already borrowed.
+    #[inline]
+    #[track_caller]
+    pub fn try_write(&self) -> TryLockResult<RwLockWriteGuard<'_, T>> {
+        match self.inner.try_write() {
+            Some(inner) => Ok(map_poison(
+                self.inner.is_poisoned(),
+                RwLockWriteGuard { inner },
+            )?),
+            None => Err(TryLockError::WouldBlock),
+        }
+    }
+
+    /// Check if the lock is poisoned.
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+
+    /// Clear the poisoned state of the lock.
+    #[inline]
+    pub fn clear_poison(&self) {
+        self.inner.clear_poison()
+    }
+
+    /// Return a mutable reference to the underlying data.
+    ///
+    /// ## Errors
+    /// If the lock is poisoned, the reference is returned inside the error.
+    #[inline]
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        let poisoned = self.inner.is_poisoned();
+        map_poison(poisoned, self.inner.get_mut())
+    }
+}
+impl<T: Default> Default for RwLock<T> {
+    #[inline]
+    fn default() -> Self {
+        RwLock::new(T::default())
+    }
+}
+impl<T> From<T> for RwLock<T> {
+    #[inline]
+    fn from(t: T) -> Self {
+        RwLock::new(t)
+    }
+}
+impl<T: ?Sized + Debug> Debug for RwLock<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("RwLock");
+        match self.inner.try_read() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.field("poisoned", &self.is_poisoned());
+        d.finish_non_exhaustive()
+    }
+}
+
+/// A guard for a shared borrow of a [RwLock],
+/// with the same API as [std::sync::RwLockReadGuard].
+#[must_use = "if unused the RwLock will immediately unlock"]
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    inner: tracked::CellRwLockReadGuard<'a, T>,
+}
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// A guard for an exclusive borrow of a [RwLock],
+/// with the same API as [std::sync::RwLockWriteGuard].
+#[must_use = "if unused the RwLock will immediately unlock"]
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    inner: tracked::CellRwLockWriteGuard<'a, T>,
+}
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl_guard_fmt!(MutexGuard<'_>, RwLockReadGuard<'_>, RwLockWriteGuard<'_>);
+
+#[cfg(test)]
+mod test {
+    use super::{Mutex, RwLock};
+    use std::sync::TryLockError;
+
+    #[test]
+    fn std_mutex() {
+        let mutex = Mutex::new(7i32);
+        *mutex.lock().unwrap() += 1;
+        {
+            let _guard = mutex.lock().unwrap();
+            assert!(matches!(mutex.try_lock(), Err(TryLockError::WouldBlock)));
+        }
+        let _ = std::panic::catch_unwind(|| {
+            let _guard = mutex.lock().unwrap();
+            panic!("poison");
+        });
+        assert!(mutex.is_poisoned());
+        let guard = mutex.lock().unwrap_err().into_inner();
+        assert_eq!(*guard, 8);
+        drop(guard);
+        assert!(matches!(mutex.try_lock(), Err(TryLockError::Poisoned(_))));
+        mutex.clear_poison();
+        assert_eq!(mutex.into_inner().unwrap(), 8);
+    }
+
+    #[test]
+    fn std_rwlock() {
+        let mut lock = RwLock::new(vec![7i32]);
+        {
+            let first = lock.read().unwrap();
+            let second = lock.try_read().unwrap();
+            assert_eq!(*first, *second);
+            assert!(matches!(lock.try_write(), Err(TryLockError::WouldBlock)));
+        }
+        lock.write().unwrap().push(8);
+        lock.get_mut().unwrap().push(9);
+        assert_eq!(
+            format!("{lock:?}"),
+            "RwLock { data: [7, 8, 9], poisoned: false, .. }"
+        );
+        let _ = std::panic::catch_unwind(|| {
+            let _guard = lock.write().unwrap();
+            panic!("poison");
+        });
+        assert!(lock.read().is_err());
+        assert_eq!(lock.into_inner().unwrap_err().into_inner(), [7, 8, 9]);
+    }
+}
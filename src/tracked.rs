@@ -0,0 +1,1838 @@
+//! Wrappers around the [lock_api] types that use `#[track_caller]` on every acquiring method.
+//!
+//! The [lock_api] methods like [lock_api::RwLock::read] are not annotated with `#[track_caller]`,
+//! so the `debug-location` feature would report a location inside lock_api
+//! instead of the code that actually acquired the lock.
+//!
+//! The types in this module call the raw lock directly,
+//! so the reported locations point at the caller.
+//! Otherwise, they behave identically to the type aliases in the crate root.
+
+use core::fmt::{self, Debug, Formatter};
+use core::marker::PhantomData;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+#[cfg(feature = "std")]
+use core::panic::{RefUnwindSafe, UnwindSafe};
+use core::ptr::NonNull;
+use core::time::Duration;
+
+use lock_api::{
+    RawMutex, RawMutexTimed, RawRwLock, RawRwLockDowngrade, RawRwLockRecursive,
+    RawRwLockRecursiveTimed, RawRwLockTimed, RawRwLockUpgrade, RawRwLockUpgradeDowngrade,
+    RawRwLockUpgradeTimed,
+};
+
+use crate::{raw, BorrowError, BorrowSnapshot};
+
+/// A single-threaded reader-writer lock, using a [RefCell](core::cell::RefCell) internally.
+///
+/// This is a wrapper around [crate::CellRwLock] that uses `#[track_caller]`,
+/// so borrow locations point to the caller instead of inside [lock_api].
+pub struct CellRwLock<T: ?Sized> {
+    inner: lock_api::RwLock<raw::CellRwLock, T>,
+}
+impl<T> CellRwLock<T> {
+    /// Create a new lock in the unlocked state.
+    #[inline]
+    pub const fn new(val: T) -> Self {
+        CellRwLock {
+            inner: lock_api::RwLock::new(val),
+        }
+    }
+
+    /// Consume this lock, returning the underlying data.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
+impl<T: ?Sized> CellRwLock<T> {
+    #[inline]
+    fn raw(&self) -> &raw::CellRwLock {
+        // SAFETY: We never use the raw lock to unlock anything we didn't lock ourselves
+        unsafe { self.inner.raw() }
+    }
+
+    /// Access the underlying [lock_api::RwLock].
+    ///
+    /// Methods called on the result will not track the location of the caller.
+    #[inline]
+    pub fn as_lock_api(&self) -> &lock_api::RwLock<raw::CellRwLock, T> {
+        &self.inner
+    }
+
+    /// Return a mutable reference to the underlying data.
+    ///
+    /// No locking is needed, because this requires a mutable reference.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+
+    /// Release any borrows leaked by the `leak` method of a guard,
+    /// like [core::cell::RefCell::undo_leak].
+    ///
+    /// This requires a mutable reference, so none of the leaked references can still be alive.
+    #[inline]
+    pub fn undo_leak(&mut self) -> &mut T {
+        // SAFETY: The mutable reference guarantees there are no other borrows
+        unsafe { self.raw().undo_leak() };
+        self.get_mut()
+    }
+
+    /// Return a raw pointer to the underlying data.
+    #[inline]
+    pub fn data_ptr(&self) -> *mut T {
+        self.inner.data_ptr()
+    }
+
+    /// Check if the lock is currently borrowed.
+    #[inline]
+    pub fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    /// Check if the lock is currently borrowed exclusively.
+    #[inline]
+    pub fn is_locked_exclusive(&self) -> bool {
+        self.inner.is_locked_exclusive()
+    }
+
+    /// Take a snapshot of the current borrow state of the lock.
+    ///
+    /// See [raw::CellRwLock::borrow_state] for details.
+    #[inline]
+    pub fn borrow_state(&self) -> BorrowSnapshot {
+        self.raw().borrow_state()
+    }
+
+    /// Check if the lock is poisoned.
+    ///
+    /// See [raw::CellRwLock::is_poisoned] for details.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.raw().is_poisoned()
+    }
+
+    /// Clear the poisoned state of the lock.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn clear_poison(&self) {
+        self.raw().clear_poison()
+    }
+
+    /// Acquire a shared borrow of the lock.
+    ///
+    /// ## Panics
+    /// If the lock is already borrowed exclusively.
+    #[inline]
+    #[track_caller]
+    pub fn read(&self) -> CellRwLockReadGuard<'_, T> {
+        self.raw().lock_shared();
+        CellRwLockReadGuard {
+            lock: self,
+            marker: PhantomData,
+        }
+    }
+
+    /// Attempt to acquire a shared borrow of the lock,
+    /// returning `None` if it is already borrowed exclusively.
+    #[inline]
+    #[track_caller]
+    pub fn try_read(&self) -> Option<CellRwLockReadGuard<'_, T>> {
+        if self.raw().try_lock_shared() {
+            Some(CellRwLockReadGuard {
+                lock: self,
+                marker: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Attempt to acquire a shared borrow of the lock,
+    /// returning a [BorrowError] describing the conflict on failure.
+    ///
+    /// This is the equivalent of [RefCell::try_borrow](core::cell::RefCell::try_borrow).
+    #[inline]
+    #[track_caller]
+    #[allow(clippy::result_large_err)]
+    pub fn try_read_err(&self) -> Result<CellRwLockReadGuard<'_, T>, BorrowError> {
+        self.raw().try_lock_shared_err()?;
+        Ok(CellRwLockReadGuard {
+            lock: self,
+            marker: PhantomData,
+        })
+    }
+
+    /// Attempt to acquire a shared borrow of the lock for a timeout,
+    /// returning `None` if it is already borrowed exclusively.
+    ///
+    /// No other thread could ever release the lock,
+    /// so this returns immediately like [Self::try_read].
+    #[inline]
+    #[track_caller]
+    pub fn try_read_for(&self, timeout: Duration) -> Option<CellRwLockReadGuard<'_, T>> {
+        if self.raw().try_lock_shared_for(timeout) {
+            Some(CellRwLockReadGuard {
+                lock: self,
+                marker: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Attempt to acquire a shared borrow of the lock until a deadline,
+    /// returning `None` if it is already borrowed exclusively.
+    ///
+    /// No other thread could ever release the lock,
+    /// so this returns immediately like [Self::try_read].
+    #[inline]
+    #[track_caller]
+    pub fn try_read_until(&self, timeout: raw::CellInstant) -> Option<CellRwLockReadGuard<'_, T>> {
+        if self.raw().try_lock_shared_until(timeout) {
+            Some(CellRwLockReadGuard {
+                lock: self,
+                marker: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Acquire a recursive shared borrow of the lock.
+    ///
+    /// See [lock_api::RwLock::read_recursive] for details.
+    ///
+    /// ## Panics
+    /// If the lock is already borrowed exclusively.
+    #[inline]
+    #[track_caller]
+    pub fn read_recursive(&self) -> CellRwLockReadGuard<'_, T> {
+        self.raw().lock_shared_recursive();
+        CellRwLockReadGuard {
+            lock: self,
+            marker: PhantomData,
+        }
+    }
+
+    /// Attempt to acquire a recursive shared borrow of the lock,
+    /// returning `None` if it is already borrowed exclusively.
+    #[inline]
+    #[track_caller]
+    pub fn try_read_recursive(&self) -> Option<CellRwLockReadGuard<'_, T>> {
+        if self.raw().try_lock_shared_recursive() {
+            Some(CellRwLockReadGuard {
+                lock: self,
+                marker: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Attempt to acquire a recursive shared borrow of the lock for a timeout,
+    /// returning `None` if it is already borrowed exclusively.
+    ///
+    /// No other thread could ever release the lock,
+    /// so this returns immediately like [Self::try_read_recursive].
+    #[inline]
+    #[track_caller]
+    pub fn try_read_recursive_for(&self, timeout: Duration) -> Option<CellRwLockReadGuard<'_, T>> {
+        if self.raw().try_lock_shared_recursive_for(timeout) {
+            Some(CellRwLockReadGuard {
+                lock: self,
+                marker: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Attempt to acquire a recursive shared borrow of the lock until a deadline,
+    /// returning `None` if it is already borrowed exclusively.
+    ///
+    /// No other thread could ever release the lock,
+    /// so this returns immediately like [Self::try_read_recursive].
+    #[inline]
+    #[track_caller]
+    pub fn try_read_recursive_until(
+        &self,
+        timeout: raw::CellInstant,
+    ) -> Option<CellRwLockReadGuard<'_, T>> {
+        if self.raw().try_lock_shared_recursive_until(timeout) {
+            Some(CellRwLockReadGuard {
+                lock: self,
+                marker: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Acquire an exclusive borrow of the lock.
+    ///
+    /// ## Panics
+    /// If the lock is already borrowed.
+    #[inline]
+    #[track_caller]
+    pub fn write(&self) -> CellRwLockWriteGuard<'_, T> {
+        self.raw().lock_exclusive();
+        CellRwLockWriteGuard {
+            lock: self,
+            marker: PhantomData,
+        }
+    }
+
+    /// Attempt to acquire an exclusive borrow of the lock,
+    /// returning `None` if it is already borrowed.
+    #[inline]
+    #[track_caller]
+    pub fn try_write(&self) -> Option<CellRwLockWriteGuard<'_, T>> {
+        if self.raw().try_lock_exclusive() {
+            Some(CellRwLockWriteGuard {
+                lock: self,
+                marker: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Attempt to acquire an exclusive borrow of the lock,
+    /// returning a [BorrowError] describing the conflict on failure.
+    ///
+    /// This is the equivalent of [RefCell::try_borrow_mut](core::cell::RefCell::try_borrow_mut).
+    #[inline]
+    #[track_caller]
+    #[allow(clippy::result_large_err)]
+    pub fn try_write_err(&self) -> Result<CellRwLockWriteGuard<'_, T>, BorrowError> {
+        self.raw().try_lock_exclusive_err()?;
+        Ok(CellRwLockWriteGuard {
+            lock: self,
+            marker: PhantomData,
+        })
+    }
+
+    /// Attempt to acquire an exclusive borrow of the lock for a timeout,
+    /// returning `None` if it is already borrowed.
+    ///
+    /// No other thread could ever release the lock,
+    /// so this returns immediately like [Self::try_write].
+    #[inline]
+    #[track_caller]
+    pub fn try_write_for(&self, timeout: Duration) -> Option<CellRwLockWriteGuard<'_, T>> {
+        if self.raw().try_lock_exclusive_for(timeout) {
+            Some(CellRwLockWriteGuard {
+                lock: self,
+                marker: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Attempt to acquire an exclusive borrow of the lock until a deadline,
+    /// returning `None` if it is already borrowed.
+    ///
+    /// No other thread could ever release the lock,
+    /// so this returns immediately like [Self::try_write].
+    #[inline]
+    #[track_caller]
+    pub fn try_write_until(
+        &self,
+        timeout: raw::CellInstant,
+    ) -> Option<CellRwLockWriteGuard<'_, T>> {
+        if self.raw().try_lock_exclusive_until(timeout) {
+            Some(CellRwLockWriteGuard {
+                lock: self,
+                marker: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Acquire an upgradable borrow of the lock.
+    ///
+    /// ## Panics
+    /// If the lock is already borrowed exclusively or upgradably.
+    #[inline]
+    #[track_caller]
+    pub fn upgradable_read(&self) -> CellRwLockUpgradableReadGuard<'_, T> {
+        self.raw().lock_upgradable();
+        CellRwLockUpgradableReadGuard {
+            lock: self,
+            marker: PhantomData,
+        }
+    }
+
+    /// Attempt to acquire an upgradable borrow of the lock,
+    /// returning `None` if it is already borrowed exclusively or upgradably.
+    #[inline]
+    #[track_caller]
+    pub fn try_upgradable_read(&self) -> Option<CellRwLockUpgradableReadGuard<'_, T>> {
+        if self.raw().try_lock_upgradable() {
+            Some(CellRwLockUpgradableReadGuard {
+                lock: self,
+                marker: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Attempt to acquire an upgradable borrow of the lock,
+    /// returning a [BorrowError] describing the conflict on failure.
+    #[inline]
+    #[track_caller]
+    #[allow(clippy::result_large_err)]
+    pub fn try_upgradable_read_err(
+        &self,
+    ) -> Result<CellRwLockUpgradableReadGuard<'_, T>, BorrowError> {
+        self.raw().try_lock_upgradable_err()?;
+        Ok(CellRwLockUpgradableReadGuard {
+            lock: self,
+            marker: PhantomData,
+        })
+    }
+
+    /// Attempt to acquire an upgradable borrow of the lock for a timeout,
+    /// returning `None` if it is already borrowed exclusively or upgradably.
+    ///
+    /// No other thread could ever release the lock,
+    /// so this returns immediately like [Self::try_upgradable_read].
+    #[inline]
+    #[track_caller]
+    pub fn try_upgradable_read_for(
+        &self,
+        timeout: Duration,
+    ) -> Option<CellRwLockUpgradableReadGuard<'_, T>> {
+        if self.raw().try_lock_upgradable_for(timeout) {
+            Some(CellRwLockUpgradableReadGuard {
+                lock: self,
+                marker: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Attempt to acquire an upgradable borrow of the lock until a deadline,
+    /// returning `None` if it is already borrowed exclusively or upgradably.
+    ///
+    /// No other thread could ever release the lock,
+    /// so this returns immediately like [Self::try_upgradable_read].
+    #[inline]
+    #[track_caller]
+    pub fn try_upgradable_read_until(
+        &self,
+        timeout: raw::CellInstant,
+    ) -> Option<CellRwLockUpgradableReadGuard<'_, T>> {
+        if self.raw().try_lock_upgradable_until(timeout) {
+            Some(CellRwLockUpgradableReadGuard {
+                lock: self,
+                marker: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+}
+/// Poisoning makes the lock unwind safe, with the same bounds as [std::sync::RwLock].
+#[cfg(feature = "std")]
+impl<T: ?Sized> UnwindSafe for CellRwLock<T> {}
+#[cfg(feature = "std")]
+impl<T: ?Sized + UnwindSafe> RefUnwindSafe for CellRwLock<T> {}
+impl<T: Default> Default for CellRwLock<T> {
+    #[inline]
+    fn default() -> Self {
+        CellRwLock::new(T::default())
+    }
+}
+impl<T> From<T> for CellRwLock<T> {
+    #[inline]
+    fn from(val: T) -> Self {
+        CellRwLock::new(val)
+    }
+}
+impl<T> From<lock_api::RwLock<raw::CellRwLock, T>> for CellRwLock<T> {
+    #[inline]
+    fn from(inner: lock_api::RwLock<raw::CellRwLock, T>) -> Self {
+        CellRwLock { inner }
+    }
+}
+impl<T: ?Sized + Debug> Debug for CellRwLock<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.inner, f)
+    }
+}
+
+/// A guard for a shared borrow of a [CellRwLock].
+#[must_use = "if unused the CellRwLock will immediately unlock"]
+pub struct CellRwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a CellRwLock<T>,
+    marker: PhantomData<&'a T>,
+}
+impl<'a, T: ?Sized> CellRwLockReadGuard<'a, T> {
+    /// Return a reference to the original lock.
+    #[inline]
+    pub fn rwlock(s: &Self) -> &'a CellRwLock<T> {
+        s.lock
+    }
+
+    /// Permanently leak the shared borrow, returning a reference to the data,
+    /// like [core::cell::Ref::leak].
+    ///
+    /// The lock can no longer be borrowed exclusively,
+    /// until [CellRwLock::undo_leak] is called.
+    /// In debug mode, later conflicts report where the borrow was leaked.
+    #[inline]
+    #[track_caller]
+    pub fn leak(s: Self) -> &'a T {
+        let s = ManuallyDrop::new(s);
+        // SAFETY: The borrow is never released, so the reference stays valid
+        unsafe {
+            s.lock.raw().leak_borrow();
+            &*s.lock.data_ptr()
+        }
+    }
+
+    /// Make a new guard for a component of the borrowed data.
+    #[inline]
+    pub fn map<U: ?Sized, F>(s: Self, f: F) -> MappedCellRwLockReadGuard<'a, U>
+    where
+        F: FnOnce(&T) -> &U,
+    {
+        // SAFETY: We hold a shared borrow
+        let data = NonNull::from(f(unsafe { &*s.lock.data_ptr() }));
+        let s = ManuallyDrop::new(s);
+        MappedCellRwLockReadGuard {
+            raw: s.lock.raw(),
+            data,
+            marker: PhantomData,
+        }
+    }
+
+    /// Split the guard into two guards for different components of the borrowed data,
+    /// like [core::cell::Ref::map_split].
+    #[inline]
+    #[track_caller]
+    pub fn map_split<U: ?Sized, V: ?Sized, F>(
+        s: Self,
+        f: F,
+    ) -> (
+        MappedCellRwLockReadGuard<'a, U>,
+        MappedCellRwLockReadGuard<'a, V>,
+    )
+    where
+        F: FnOnce(&T) -> (&U, &V),
+    {
+        // SAFETY: We hold a shared borrow
+        let (first, second) = f(unsafe { &*s.lock.data_ptr() });
+        let (first, second) = (NonNull::from(first), NonNull::from(second));
+        let s = ManuallyDrop::new(s);
+        // SAFETY: Each guard releases one of the borrows
+        unsafe { s.lock.raw().split_shared() };
+        (
+            MappedCellRwLockReadGuard {
+                raw: s.lock.raw(),
+                data: first,
+                marker: PhantomData,
+            },
+            MappedCellRwLockReadGuard {
+                raw: s.lock.raw(),
+                data: second,
+                marker: PhantomData,
+            },
+        )
+    }
+
+    /// Attempt to make a new guard for a component of the borrowed data,
+    /// returning the original guard if the closure returns `None`.
+    #[inline]
+    pub fn try_map<U: ?Sized, F>(s: Self, f: F) -> Result<MappedCellRwLockReadGuard<'a, U>, Self>
+    where
+        F: FnOnce(&T) -> Option<&U>,
+    {
+        // SAFETY: We hold a shared borrow
+        match f(unsafe { &*s.lock.data_ptr() }) {
+            Some(data) => {
+                let data = NonNull::from(data);
+                let s = ManuallyDrop::new(s);
+                Ok(MappedCellRwLockReadGuard {
+                    raw: s.lock.raw(),
+                    data,
+                    marker: PhantomData,
+                })
+            }
+            None => Err(s),
+        }
+    }
+}
+impl<T: ?Sized> Deref for CellRwLockReadGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // SAFETY: We hold a shared borrow
+        unsafe { &*self.lock.data_ptr() }
+    }
+}
+impl<T: ?Sized> Drop for CellRwLockReadGuard<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: We hold a shared borrow
+        unsafe { self.lock.raw().unlock_shared() }
+    }
+}
+
+/// A guard for an exclusive borrow of a [CellRwLock].
+#[must_use = "if unused the CellRwLock will immediately unlock"]
+pub struct CellRwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a CellRwLock<T>,
+    marker: PhantomData<&'a mut T>,
+}
+impl<'a, T: ?Sized> CellRwLockWriteGuard<'a, T> {
+    /// Return a reference to the original lock.
+    #[inline]
+    pub fn rwlock(s: &Self) -> &'a CellRwLock<T> {
+        s.lock
+    }
+
+    /// Permanently leak the exclusive borrow, returning a reference to the data,
+    /// like [core::cell::RefMut::leak].
+    ///
+    /// The lock can no longer be borrowed,
+    /// until [CellRwLock::undo_leak] is called.
+    /// In debug mode, later conflicts report where the borrow was leaked.
+    #[inline]
+    #[track_caller]
+    pub fn leak(s: Self) -> &'a mut T {
+        let s = ManuallyDrop::new(s);
+        // SAFETY: The borrow is never released, so the reference stays valid
+        unsafe {
+            s.lock.raw().leak_borrow();
+            &mut *s.lock.data_ptr()
+        }
+    }
+
+    /// Make a new guard for a component of the borrowed data.
+    #[inline]
+    pub fn map<U: ?Sized, F>(s: Self, f: F) -> MappedCellRwLockWriteGuard<'a, U>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        // SAFETY: We hold an exclusive borrow
+        let data = NonNull::from(f(unsafe { &mut *s.lock.data_ptr() }));
+        let s = ManuallyDrop::new(s);
+        MappedCellRwLockWriteGuard {
+            raw: s.lock.raw(),
+            data,
+            marker: PhantomData,
+        }
+    }
+
+    /// Split the guard into two guards for disjoint components of the borrowed data,
+    /// like [core::cell::RefMut::map_split].
+    #[inline]
+    #[track_caller]
+    pub fn map_split<U: ?Sized, V: ?Sized, F>(
+        s: Self,
+        f: F,
+    ) -> (
+        MappedCellRwLockWriteGuard<'a, U>,
+        MappedCellRwLockWriteGuard<'a, V>,
+    )
+    where
+        F: FnOnce(&mut T) -> (&mut U, &mut V),
+    {
+        // SAFETY: We hold an exclusive borrow
+        let (first, second) = f(unsafe { &mut *s.lock.data_ptr() });
+        let (first, second) = (NonNull::from(first), NonNull::from(second));
+        let s = ManuallyDrop::new(s);
+        // SAFETY: Each guard releases one of the borrows,
+        // and the borrow checker ensures the components are disjoint
+        unsafe { s.lock.raw().split_exclusive() };
+        (
+            MappedCellRwLockWriteGuard {
+                raw: s.lock.raw(),
+                data: first,
+                marker: PhantomData,
+            },
+            MappedCellRwLockWriteGuard {
+                raw: s.lock.raw(),
+                data: second,
+                marker: PhantomData,
+            },
+        )
+    }
+
+    /// Attempt to make a new guard for a component of the borrowed data,
+    /// returning the original guard if the closure returns `None`.
+    #[inline]
+    pub fn try_map<U: ?Sized, F>(s: Self, f: F) -> Result<MappedCellRwLockWriteGuard<'a, U>, Self>
+    where
+        F: FnOnce(&mut T) -> Option<&mut U>,
+    {
+        // SAFETY: We hold an exclusive borrow
+        match f(unsafe { &mut *s.lock.data_ptr() }) {
+            Some(data) => {
+                let data = NonNull::from(data);
+                let s = ManuallyDrop::new(s);
+                Ok(MappedCellRwLockWriteGuard {
+                    raw: s.lock.raw(),
+                    data,
+                    marker: PhantomData,
+                })
+            }
+            None => Err(s),
+        }
+    }
+
+    /// Downgrade the exclusive borrow into a shared borrow.
+    #[inline]
+    pub fn downgrade(s: Self) -> CellRwLockReadGuard<'a, T> {
+        let s = ManuallyDrop::new(s);
+        // SAFETY: We hold an exclusive borrow
+        unsafe { s.lock.raw().downgrade() };
+        CellRwLockReadGuard {
+            lock: s.lock,
+            marker: PhantomData,
+        }
+    }
+
+    /// Downgrade the exclusive borrow into an upgradable borrow.
+    #[inline]
+    pub fn downgrade_to_upgradable(s: Self) -> CellRwLockUpgradableReadGuard<'a, T> {
+        let s = ManuallyDrop::new(s);
+        // SAFETY: We hold an exclusive borrow
+        unsafe { s.lock.raw().downgrade_to_upgradable() };
+        CellRwLockUpgradableReadGuard {
+            lock: s.lock,
+            marker: PhantomData,
+        }
+    }
+}
+impl<T: ?Sized> Deref for CellRwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // SAFETY: We hold an exclusive borrow
+        unsafe { &*self.lock.data_ptr() }
+    }
+}
+impl<T: ?Sized> DerefMut for CellRwLockWriteGuard<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: We hold an exclusive borrow
+        unsafe { &mut *self.lock.data_ptr() }
+    }
+}
+impl<T: ?Sized> Drop for CellRwLockWriteGuard<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: We hold an exclusive borrow
+        unsafe { self.lock.raw().unlock_exclusive() }
+    }
+}
+
+/// A guard for an upgradable borrow of a [CellRwLock].
+#[must_use = "if unused the CellRwLock will immediately unlock"]
+pub struct CellRwLockUpgradableReadGuard<'a, T: ?Sized> {
+    lock: &'a CellRwLock<T>,
+    marker: PhantomData<&'a T>,
+}
+impl<'a, T: ?Sized> CellRwLockUpgradableReadGuard<'a, T> {
+    /// Return a reference to the original lock.
+    #[inline]
+    pub fn rwlock(s: &Self) -> &'a CellRwLock<T> {
+        s.lock
+    }
+
+    /// Upgrade the borrow into an exclusive borrow.
+    ///
+    /// ## Panics
+    /// If any other shared borrows are still active,
+    /// because a real lock would deadlock waiting for them.
+    #[inline]
+    #[track_caller]
+    pub fn upgrade(s: Self) -> CellRwLockWriteGuard<'a, T> {
+        // SAFETY: We hold an upgradable borrow
+        unsafe { s.lock.raw().upgrade() };
+        let s = ManuallyDrop::new(s);
+        CellRwLockWriteGuard {
+            lock: s.lock,
+            marker: PhantomData,
+        }
+    }
+
+    /// Attempt to upgrade the borrow into an exclusive borrow,
+    /// returning the original guard if other shared borrows are still active.
+    #[inline]
+    #[track_caller]
+    pub fn try_upgrade(s: Self) -> Result<CellRwLockWriteGuard<'a, T>, Self> {
+        // SAFETY: We hold an upgradable borrow
+        if unsafe { s.lock.raw().try_upgrade() } {
+            let s = ManuallyDrop::new(s);
+            Ok(CellRwLockWriteGuard {
+                lock: s.lock,
+                marker: PhantomData,
+            })
+        } else {
+            Err(s)
+        }
+    }
+
+    /// Downgrade the upgradable borrow into an ordinary shared borrow.
+    #[inline]
+    pub fn downgrade(s: Self) -> CellRwLockReadGuard<'a, T> {
+        let s = ManuallyDrop::new(s);
+        // SAFETY: We hold an upgradable borrow
+        unsafe { s.lock.raw().downgrade_upgradable() };
+        CellRwLockReadGuard {
+            lock: s.lock,
+            marker: PhantomData,
+        }
+    }
+}
+impl<T: ?Sized> Deref for CellRwLockUpgradableReadGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // SAFETY: We hold an upgradable borrow
+        unsafe { &*self.lock.data_ptr() }
+    }
+}
+impl<T: ?Sized> Drop for CellRwLockUpgradableReadGuard<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: We hold an upgradable borrow
+        unsafe { self.lock.raw().unlock_upgradable() }
+    }
+}
+
+/// A guard for a shared borrow of a component of a [CellRwLock].
+///
+/// Created by [CellRwLockReadGuard::map].
+#[must_use = "if unused the CellRwLock will immediately unlock"]
+pub struct MappedCellRwLockReadGuard<'a, T: ?Sized> {
+    raw: &'a raw::CellRwLock,
+    data: NonNull<T>,
+    marker: PhantomData<&'a T>,
+}
+impl<'a, T: ?Sized> MappedCellRwLockReadGuard<'a, T> {
+    /// Permanently leak the shared borrow, returning a reference to the data,
+    /// like [core::cell::Ref::leak].
+    ///
+    /// See [CellRwLockReadGuard::leak] for details.
+    #[inline]
+    #[track_caller]
+    pub fn leak(s: Self) -> &'a T {
+        let s = ManuallyDrop::new(s);
+        // SAFETY: The borrow is never released, so the reference stays valid
+        unsafe {
+            s.raw.leak_borrow();
+            s.data.as_ref()
+        }
+    }
+
+    /// Make a new guard for a component of the borrowed data.
+    #[inline]
+    pub fn map<U: ?Sized, F>(s: Self, f: F) -> MappedCellRwLockReadGuard<'a, U>
+    where
+        F: FnOnce(&T) -> &U,
+    {
+        // SAFETY: We hold a shared borrow
+        let data = NonNull::from(f(unsafe { s.data.as_ref() }));
+        let s = ManuallyDrop::new(s);
+        MappedCellRwLockReadGuard {
+            raw: s.raw,
+            data,
+            marker: PhantomData,
+        }
+    }
+
+    /// Split the guard into two guards for different components of the borrowed data,
+    /// like [core::cell::Ref::map_split].
+    #[inline]
+    #[track_caller]
+    pub fn map_split<U: ?Sized, V: ?Sized, F>(
+        s: Self,
+        f: F,
+    ) -> (
+        MappedCellRwLockReadGuard<'a, U>,
+        MappedCellRwLockReadGuard<'a, V>,
+    )
+    where
+        F: FnOnce(&T) -> (&U, &V),
+    {
+        // SAFETY: We hold a shared borrow
+        let (first, second) = f(unsafe { s.data.as_ref() });
+        let (first, second) = (NonNull::from(first), NonNull::from(second));
+        let s = ManuallyDrop::new(s);
+        // SAFETY: Each guard releases one of the borrows
+        unsafe { s.raw.split_shared() };
+        (
+            MappedCellRwLockReadGuard {
+                raw: s.raw,
+                data: first,
+                marker: PhantomData,
+            },
+            MappedCellRwLockReadGuard {
+                raw: s.raw,
+                data: second,
+                marker: PhantomData,
+            },
+        )
+    }
+
+    /// Attempt to make a new guard for a component of the borrowed data,
+    /// returning the original guard if the closure returns `None`.
+    #[inline]
+    pub fn try_map<U: ?Sized, F>(s: Self, f: F) -> Result<MappedCellRwLockReadGuard<'a, U>, Self>
+    where
+        F: FnOnce(&T) -> Option<&U>,
+    {
+        // SAFETY: We hold a shared borrow
+        match f(unsafe { s.data.as_ref() }) {
+            Some(data) => {
+                let data = NonNull::from(data);
+                let s = ManuallyDrop::new(s);
+                Ok(MappedCellRwLockReadGuard {
+                    raw: s.raw,
+                    data,
+                    marker: PhantomData,
+                })
+            }
+            None => Err(s),
+        }
+    }
+}
+impl<T: ?Sized> Deref for MappedCellRwLockReadGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // SAFETY: We hold a shared borrow
+        unsafe { self.data.as_ref() }
+    }
+}
+impl<T: ?Sized> Drop for MappedCellRwLockReadGuard<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: We hold a shared borrow
+        unsafe { self.raw.unlock_shared() }
+    }
+}
+
+/// A guard for an exclusive borrow of a component of a [CellRwLock].
+///
+/// Created by [CellRwLockWriteGuard::map].
+#[must_use = "if unused the CellRwLock will immediately unlock"]
+pub struct MappedCellRwLockWriteGuard<'a, T: ?Sized> {
+    raw: &'a raw::CellRwLock,
+    data: NonNull<T>,
+    marker: PhantomData<&'a mut T>,
+}
+impl<'a, T: ?Sized> MappedCellRwLockWriteGuard<'a, T> {
+    /// Permanently leak the exclusive borrow, returning a reference to the data,
+    /// like [core::cell::RefMut::leak].
+    ///
+    /// See [CellRwLockWriteGuard::leak] for details.
+    #[inline]
+    #[track_caller]
+    pub fn leak(s: Self) -> &'a mut T {
+        let mut s = ManuallyDrop::new(s);
+        // SAFETY: The borrow is never released, so the reference stays valid
+        unsafe {
+            s.raw.leak_borrow();
+            s.data.as_mut()
+        }
+    }
+
+    /// Make a new guard for a component of the borrowed data.
+    #[inline]
+    pub fn map<U: ?Sized, F>(mut s: Self, f: F) -> MappedCellRwLockWriteGuard<'a, U>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        // SAFETY: We hold an exclusive borrow
+        let data = NonNull::from(f(unsafe { s.data.as_mut() }));
+        let s = ManuallyDrop::new(s);
+        MappedCellRwLockWriteGuard {
+            raw: s.raw,
+            data,
+            marker: PhantomData,
+        }
+    }
+
+    /// Split the guard into two guards for disjoint components of the borrowed data,
+    /// like [core::cell::RefMut::map_split].
+    #[inline]
+    #[track_caller]
+    pub fn map_split<U: ?Sized, V: ?Sized, F>(
+        mut s: Self,
+        f: F,
+    ) -> (
+        MappedCellRwLockWriteGuard<'a, U>,
+        MappedCellRwLockWriteGuard<'a, V>,
+    )
+    where
+        F: FnOnce(&mut T) -> (&mut U, &mut V),
+    {
+        // SAFETY: We hold an exclusive borrow
+        let (first, second) = f(unsafe { s.data.as_mut() });
+        let (first, second) = (NonNull::from(first), NonNull::from(second));
+        let s = ManuallyDrop::new(s);
+        // SAFETY: Each guard releases one of the borrows,
+        // and the borrow checker ensures the components are disjoint
+        unsafe { s.raw.split_exclusive() };
+        (
+            MappedCellRwLockWriteGuard {
+                raw: s.raw,
+                data: first,
+                marker: PhantomData,
+            },
+            MappedCellRwLockWriteGuard {
+                raw: s.raw,
+                data: second,
+                marker: PhantomData,
+            },
+        )
+    }
+
+    /// Attempt to make a new guard for a component of the borrowed data,
+    /// returning the original guard if the closure returns `None`.
+    #[inline]
+    pub fn try_map<U: ?Sized, F>(
+        mut s: Self,
+        f: F,
+    ) -> Result<MappedCellRwLockWriteGuard<'a, U>, Self>
+    where
+        F: FnOnce(&mut T) -> Option<&mut U>,
+    {
+        // SAFETY: We hold an exclusive borrow
+        match f(unsafe { s.data.as_mut() }) {
+            Some(data) => {
+                let data = NonNull::from(data);
+                let s = ManuallyDrop::new(s);
+                Ok(MappedCellRwLockWriteGuard {
+                    raw: s.raw,
+                    data,
+                    marker: PhantomData,
+                })
+            }
+            None => Err(s),
+        }
+    }
+}
+impl<T: ?Sized> Deref for MappedCellRwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // SAFETY: We hold an exclusive borrow
+        unsafe { self.data.as_ref() }
+    }
+}
+impl<T: ?Sized> DerefMut for MappedCellRwLockWriteGuard<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: We hold an exclusive borrow
+        unsafe { self.data.as_mut() }
+    }
+}
+impl<T: ?Sized> Drop for MappedCellRwLockWriteGuard<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: We hold an exclusive borrow
+        unsafe { self.raw.unlock_exclusive() }
+    }
+}
+
+/// A single-threaded mutex, using a [RefCell](core::cell::RefCell) internally.
+///
+/// This is a wrapper around [crate::CellMutex] that uses `#[track_caller]`,
+/// so borrow locations point to the caller instead of inside [lock_api].
+pub struct CellMutex<T: ?Sized> {
+    inner: lock_api::Mutex<raw::CellMutex, T>,
+}
+impl<T> CellMutex<T> {
+    /// Create a new mutex in the unlocked state.
+    #[inline]
+    pub const fn new(val: T) -> Self {
+        CellMutex {
+            inner: lock_api::Mutex::new(val),
+        }
+    }
+
+    /// Consume this mutex, returning the underlying data.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
+impl<T: ?Sized> CellMutex<T> {
+    #[inline]
+    fn raw(&self) -> &raw::CellMutex {
+        // SAFETY: We never use the raw lock to unlock anything we didn't lock ourselves
+        unsafe { self.inner.raw() }
+    }
+
+    /// Access the underlying [lock_api::Mutex].
+    ///
+    /// Methods called on the result will not track the location of the caller.
+    #[inline]
+    pub fn as_lock_api(&self) -> &lock_api::Mutex<raw::CellMutex, T> {
+        &self.inner
+    }
+
+    /// Return a mutable reference to the underlying data.
+    ///
+    /// No locking is needed, because this requires a mutable reference.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+
+    /// Unlock the mutex if it was leaked by the `leak` method of a guard,
+    /// like [core::cell::RefCell::undo_leak].
+    ///
+    /// This requires a mutable reference, so the leaked reference can no longer be alive.
+    #[inline]
+    pub fn undo_leak(&mut self) -> &mut T {
+        // SAFETY: The mutable reference guarantees there is no other borrow
+        unsafe { self.raw().undo_leak() };
+        self.get_mut()
+    }
+
+    /// Return a raw pointer to the underlying data.
+    #[inline]
+    pub fn data_ptr(&self) -> *mut T {
+        self.inner.data_ptr()
+    }
+
+    /// Check if the mutex is currently locked.
+    #[inline]
+    pub fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    /// Take a snapshot of the current borrow state of the mutex.
+    ///
+    /// See [raw::CellRwLock::borrow_state] for details.
+    #[inline]
+    pub fn borrow_state(&self) -> BorrowSnapshot {
+        self.raw().borrow_state()
+    }
+
+    /// Check if the mutex is poisoned.
+    ///
+    /// See [raw::CellRwLock::is_poisoned] for details.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.raw().is_poisoned()
+    }
+
+    /// Clear the poisoned state of the mutex.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn clear_poison(&self) {
+        self.raw().clear_poison()
+    }
+
+    /// Lock the mutex.
+    ///
+    /// ## Panics
+    /// If the mutex is already locked.
+    #[inline]
+    #[track_caller]
+    pub fn lock(&self) -> CellMutexGuard<'_, T> {
+        self.raw().lock();
+        CellMutexGuard {
+            mutex: self,
+            marker: PhantomData,
+        }
+    }
+
+    /// Attempt to lock the mutex,
+    /// returning `None` if it is already locked.
+    #[inline]
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<CellMutexGuard<'_, T>> {
+        if self.raw().try_lock() {
+            Some(CellMutexGuard {
+                mutex: self,
+                marker: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Attempt to lock the mutex,
+    /// returning a [BorrowError] describing the conflict on failure.
+    #[inline]
+    #[track_caller]
+    #[allow(clippy::result_large_err)]
+    pub fn try_lock_err(&self) -> Result<CellMutexGuard<'_, T>, BorrowError> {
+        self.raw().try_lock_err()?;
+        Ok(CellMutexGuard {
+            mutex: self,
+            marker: PhantomData,
+        })
+    }
+
+    /// Attempt to lock the mutex for a timeout,
+    /// returning `None` if it is already locked.
+    ///
+    /// No other thread could ever unlock the mutex,
+    /// so this returns immediately like [Self::try_lock].
+    #[inline]
+    #[track_caller]
+    pub fn try_lock_for(&self, timeout: Duration) -> Option<CellMutexGuard<'_, T>> {
+        if self.raw().try_lock_for(timeout) {
+            Some(CellMutexGuard {
+                mutex: self,
+                marker: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Attempt to lock the mutex until a deadline,
+    /// returning `None` if it is already locked.
+    ///
+    /// No other thread could ever unlock the mutex,
+    /// so this returns immediately like [Self::try_lock].
+    #[inline]
+    #[track_caller]
+    pub fn try_lock_until(&self, timeout: raw::CellInstant) -> Option<CellMutexGuard<'_, T>> {
+        if self.raw().try_lock_until(timeout) {
+            Some(CellMutexGuard {
+                mutex: self,
+                marker: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+}
+/// Poisoning makes the lock unwind safe, with the same bounds as [std::sync::Mutex].
+#[cfg(feature = "std")]
+impl<T: ?Sized> UnwindSafe for CellMutex<T> {}
+#[cfg(feature = "std")]
+impl<T: ?Sized + UnwindSafe> RefUnwindSafe for CellMutex<T> {}
+impl<T: Default> Default for CellMutex<T> {
+    #[inline]
+    fn default() -> Self {
+        CellMutex::new(T::default())
+    }
+}
+impl<T> From<T> for CellMutex<T> {
+    #[inline]
+    fn from(val: T) -> Self {
+        CellMutex::new(val)
+    }
+}
+impl<T> From<lock_api::Mutex<raw::CellMutex, T>> for CellMutex<T> {
+    #[inline]
+    fn from(inner: lock_api::Mutex<raw::CellMutex, T>) -> Self {
+        CellMutex { inner }
+    }
+}
+impl<T: ?Sized + Debug> Debug for CellMutex<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.inner, f)
+    }
+}
+
+/// A guard for a locked [CellMutex].
+#[must_use = "if unused the CellMutex will immediately unlock"]
+pub struct CellMutexGuard<'a, T: ?Sized> {
+    mutex: &'a CellMutex<T>,
+    marker: PhantomData<&'a mut T>,
+}
+impl<'a, T: ?Sized> CellMutexGuard<'a, T> {
+    /// Return a reference to the original mutex.
+    #[inline]
+    pub fn mutex(s: &Self) -> &'a CellMutex<T> {
+        s.mutex
+    }
+
+    /// Permanently leak the lock, returning a reference to the data,
+    /// like [core::cell::RefMut::leak].
+    ///
+    /// The mutex stays locked until [CellMutex::undo_leak] is called.
+    /// In debug mode, later conflicts report where the lock was leaked.
+    #[inline]
+    #[track_caller]
+    pub fn leak(s: Self) -> &'a mut T {
+        let s = ManuallyDrop::new(s);
+        // SAFETY: The lock is never released, so the reference stays valid
+        unsafe {
+            s.mutex.raw().leak_borrow();
+            &mut *s.mutex.data_ptr()
+        }
+    }
+
+    /// Make a new guard for a component of the locked data.
+    #[inline]
+    pub fn map<U: ?Sized, F>(s: Self, f: F) -> MappedCellMutexGuard<'a, U>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        // SAFETY: We hold the lock
+        let data = NonNull::from(f(unsafe { &mut *s.mutex.data_ptr() }));
+        let s = ManuallyDrop::new(s);
+        MappedCellMutexGuard {
+            raw: s.mutex.raw(),
+            data,
+            marker: PhantomData,
+        }
+    }
+
+    /// Attempt to make a new guard for a component of the locked data,
+    /// returning the original guard if the closure returns `None`.
+    #[inline]
+    pub fn try_map<U: ?Sized, F>(s: Self, f: F) -> Result<MappedCellMutexGuard<'a, U>, Self>
+    where
+        F: FnOnce(&mut T) -> Option<&mut U>,
+    {
+        // SAFETY: We hold the lock
+        match f(unsafe { &mut *s.mutex.data_ptr() }) {
+            Some(data) => {
+                let data = NonNull::from(data);
+                let s = ManuallyDrop::new(s);
+                Ok(MappedCellMutexGuard {
+                    raw: s.mutex.raw(),
+                    data,
+                    marker: PhantomData,
+                })
+            }
+            None => Err(s),
+        }
+    }
+}
+impl<T: ?Sized> Deref for CellMutexGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // SAFETY: We hold the lock
+        unsafe { &*self.mutex.data_ptr() }
+    }
+}
+impl<T: ?Sized> DerefMut for CellMutexGuard<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: We hold the lock
+        unsafe { &mut *self.mutex.data_ptr() }
+    }
+}
+impl<T: ?Sized> Drop for CellMutexGuard<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: We hold the lock
+        unsafe { self.mutex.raw().unlock() }
+    }
+}
+
+/// A guard for a locked component of a [CellMutex].
+///
+/// Created by [CellMutexGuard::map].
+#[must_use = "if unused the CellMutex will immediately unlock"]
+pub struct MappedCellMutexGuard<'a, T: ?Sized> {
+    raw: &'a raw::CellMutex,
+    data: NonNull<T>,
+    marker: PhantomData<&'a mut T>,
+}
+impl<'a, T: ?Sized> MappedCellMutexGuard<'a, T> {
+    /// Permanently leak the lock, returning a reference to the data,
+    /// like [core::cell::RefMut::leak].
+    ///
+    /// See [CellMutexGuard::leak] for details.
+    #[inline]
+    #[track_caller]
+    pub fn leak(s: Self) -> &'a mut T {
+        let mut s = ManuallyDrop::new(s);
+        // SAFETY: The lock is never released, so the reference stays valid
+        unsafe {
+            s.raw.leak_borrow();
+            s.data.as_mut()
+        }
+    }
+
+    /// Make a new guard for a component of the locked data.
+    #[inline]
+    pub fn map<U: ?Sized, F>(mut s: Self, f: F) -> MappedCellMutexGuard<'a, U>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        // SAFETY: We hold the lock
+        let data = NonNull::from(f(unsafe { s.data.as_mut() }));
+        let s = ManuallyDrop::new(s);
+        MappedCellMutexGuard {
+            raw: s.raw,
+            data,
+            marker: PhantomData,
+        }
+    }
+
+    /// Attempt to make a new guard for a component of the locked data,
+    /// returning the original guard if the closure returns `None`.
+    #[inline]
+    pub fn try_map<U: ?Sized, F>(mut s: Self, f: F) -> Result<MappedCellMutexGuard<'a, U>, Self>
+    where
+        F: FnOnce(&mut T) -> Option<&mut U>,
+    {
+        // SAFETY: We hold the lock
+        match f(unsafe { s.data.as_mut() }) {
+            Some(data) => {
+                let data = NonNull::from(data);
+                let s = ManuallyDrop::new(s);
+                Ok(MappedCellMutexGuard {
+                    raw: s.raw,
+                    data,
+                    marker: PhantomData,
+                })
+            }
+            None => Err(s),
+        }
+    }
+}
+impl<T: ?Sized> Deref for MappedCellMutexGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // SAFETY: We hold the lock
+        unsafe { self.data.as_ref() }
+    }
+}
+impl<T: ?Sized> DerefMut for MappedCellMutexGuard<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: We hold the lock
+        unsafe { self.data.as_mut() }
+    }
+}
+impl<T: ?Sized> Drop for MappedCellMutexGuard<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: We hold the lock
+        unsafe { self.raw.unlock() }
+    }
+}
+
+impl_guard_fmt!(
+    CellRwLockReadGuard<'_>,
+    CellRwLockWriteGuard<'_>,
+    CellRwLockUpgradableReadGuard<'_>,
+    MappedCellRwLockReadGuard<'_>,
+    MappedCellRwLockWriteGuard<'_>,
+    CellMutexGuard<'_>,
+    MappedCellMutexGuard<'_>
+);
+
+#[cfg(test)]
+mod test {
+    use super::{CellMutex, CellMutexGuard, CellRwLock, CellRwLockReadGuard, CellRwLockWriteGuard};
+    use super::{MappedCellMutexGuard, MappedCellRwLockReadGuard, MappedCellRwLockWriteGuard};
+    use crate::{raw, BorrowKind, BorrowRequest, BorrowStatus};
+    use core::time::Duration;
+
+    #[test]
+    fn tracked_guards() {
+        let lock = CellRwLock::new((7i32, vec![18i32]));
+        {
+            let guard = lock.read();
+            let first = CellRwLockReadGuard::map(guard, |(first, _)| first);
+            assert_eq!(*first, 7);
+            assert!(lock.try_write().is_none());
+        }
+        {
+            let guard = lock.write();
+            let mut second = CellRwLockWriteGuard::map(guard, |(_, second)| second);
+            second.push(19);
+            assert!(lock.try_read().is_none());
+        }
+        assert_eq!(lock.into_inner(), (7, vec![18, 19]));
+        let mutex = CellMutex::new(7i32);
+        {
+            let mut guard = CellMutexGuard::map(mutex.lock(), |val| val);
+            *guard += 1;
+            assert!(mutex.try_lock().is_none());
+        }
+        assert_eq!(mutex.into_inner(), 8);
+    }
+
+    #[test]
+    fn map_panic() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+        let lock = CellRwLock::new(7i32);
+        let mutex = CellMutex::new(7i32);
+        catch_unwind(AssertUnwindSafe(|| {
+            CellRwLockReadGuard::map(lock.read(), |_| -> &i32 { panic!("map") })
+        }))
+        .unwrap_err();
+        catch_unwind(AssertUnwindSafe(|| {
+            let guard = CellRwLockWriteGuard::map(lock.write(), |val| val);
+            MappedCellRwLockWriteGuard::map(guard, |_| -> &mut i32 { panic!("map") })
+        }))
+        .unwrap_err();
+        assert!(!lock.is_locked());
+        catch_unwind(AssertUnwindSafe(|| {
+            let guard = CellMutexGuard::map(mutex.lock(), |val| val);
+            MappedCellMutexGuard::map(guard, |_| -> &mut i32 { panic!("map") })
+        }))
+        .unwrap_err();
+        assert!(!mutex.is_locked());
+    }
+
+    #[test]
+    #[cfg(debug_location)]
+    #[should_panic(expected = "tracked.rs")]
+    fn tracked_location() {
+        let _tracking = crate::tracking::enable_for_test();
+        let lock = CellRwLock::new(());
+        let _guard = lock.write();
+        let _other = lock.read();
+    }
+
+    #[test]
+    #[cfg(debug_location)]
+    fn all_borrow_locations() {
+        let _tracking = crate::tracking::enable_for_test();
+        let lock = CellRwLock::new(());
+        let _first = lock.read();
+        let _second = lock.read_recursive();
+        let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _ = lock.write();
+        }))
+        .unwrap_err();
+        // Skip any backtraces, which may also mention this file
+        let message = payload
+            .downcast_ref::<String>()
+            .unwrap()
+            .lines()
+            .next()
+            .unwrap();
+        assert!(message.contains("2 reader(s)"), "{message}");
+        // The requested location, followed by both existing borrows
+        assert_eq!(message.matches("tracked.rs").count(), 3, "{message}");
+    }
+
+    #[test]
+    fn try_borrow_err() {
+        #[cfg(debug_location)]
+        let _tracking = crate::tracking::enable_for_test();
+        let lock = CellRwLock::new(7i32);
+        {
+            let _guard = lock.read();
+            let err = lock.try_write_err().unwrap_err();
+            assert_eq!(err.request(), BorrowRequest::Exclusive);
+            assert_eq!(err.held_kind(), BorrowKind::Shared);
+            assert_eq!(err.held_count(), 1);
+            assert!(!err.is_mutex());
+            assert!(lock.try_read_err().is_ok());
+        }
+        let mutex = CellMutex::new(7i32);
+        let _guard = mutex.lock();
+        let err = mutex.try_lock_err().unwrap_err();
+        assert!(err.is_mutex());
+        assert_eq!(err.held_kind(), BorrowKind::Exclusive);
+        #[cfg(debug_location)]
+        assert_eq!(err.held_locations().count(), 1);
+        let _: &dyn core::error::Error = &err;
+    }
+
+    #[test]
+    fn timed_locks() {
+        #[cfg(debug_location)]
+        let _tracking = crate::tracking::enable_for_test();
+        let lock = CellRwLock::new(7i32);
+        {
+            let _guard = lock.try_read_for(Duration::from_secs(1)).unwrap();
+            let line = line!() - 1;
+            if cfg!(debug_location) {
+                let location = lock.borrow_state().locations().next().unwrap();
+                assert_eq!((location.file(), location.line()), (file!(), line));
+            }
+            assert!(lock.try_read_recursive_for(Duration::ZERO).is_some());
+            assert!(lock
+                .try_upgradable_read_until(raw::CellInstant::now())
+                .is_some());
+            assert!(lock.try_write_for(Duration::from_secs(60)).is_none());
+        }
+        let deadline = raw::CellInstant::now() + Duration::from_secs(60);
+        assert!(lock.try_read_until(deadline).is_some());
+        assert!(lock.try_read_recursive_until(deadline).is_some());
+        assert!(lock.try_upgradable_read_for(Duration::ZERO).is_some());
+        let _guard = lock.try_write_until(deadline).unwrap();
+
+        let mutex = CellMutex::new(7i32);
+        let _guard = mutex.try_lock_until(deadline).unwrap();
+        assert!(mutex.try_lock_for(Duration::from_secs(60)).is_none());
+    }
+
+    #[test]
+    fn borrow_state() {
+        #[cfg(debug_location)]
+        let _tracking = crate::tracking::enable_for_test();
+        let lock = CellRwLock::new(7i32);
+        assert!(lock.borrow_state().is_unused());
+        {
+            let _first = lock.read();
+            let _second = lock.upgradable_read();
+            let state = lock.borrow_state();
+            assert_eq!(
+                state.status(),
+                BorrowStatus::Shared {
+                    readers: 2,
+                    upgradable: true
+                }
+            );
+            assert_eq!(state.kind(), Some(BorrowKind::Upgradable));
+            #[cfg(debug_location)]
+            assert_eq!(state.locations().count(), 2);
+        }
+        let _guard = lock.write();
+        assert_eq!(lock.borrow_state().status(), BorrowStatus::Exclusive);
+        let debug = format!("{:?}", unsafe { lock.as_lock_api().raw() });
+        assert!(debug.contains("Exclusive"), "{debug}");
+    }
+
+    #[test]
+    #[cfg(all(feature = "std", debug_assertions))]
+    #[should_panic(expected = "CellRwLock dropped while still exclusively borrowed")]
+    fn leaked_write_guard() {
+        let lock = CellRwLock::new(7i32);
+        core::mem::forget(lock.write());
+        drop(lock);
+    }
+
+    #[test]
+    #[cfg(all(feature = "std", debug_assertions))]
+    #[should_panic(expected = "CellMutex dropped while still locked")]
+    fn leaked_mutex_guard() {
+        let mutex = CellMutex::new(7i32);
+        core::mem::forget(mutex.lock());
+        mutex.into_inner();
+    }
+
+    #[test]
+    fn leaked_guard_while_panicking() {
+        let lock = CellRwLock::new(7i32);
+        core::mem::forget(lock.write());
+        let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+            let _lock = lock;
+            panic!("original panic");
+        }))
+        .unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"original panic"));
+    }
+
+    #[test]
+    #[cfg(all(feature = "std", debug_location, debug_assertions))]
+    fn leaked_guard_location() {
+        let _tracking = crate::tracking::enable_for_test();
+        let lock = CellRwLock::new(7i32);
+        core::mem::forget(lock.read());
+        let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            lock.into_inner();
+        }))
+        .unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+        assert!(message.contains("borrowed by 1 reader(s) at"), "{message}");
+        assert!(message.contains(file!()), "{message}");
+    }
+
+    #[test]
+    #[cfg(all(feature = "std", debug_location))]
+    fn held_backtraces() {
+        use std::backtrace::{Backtrace, BacktraceStatus};
+        let _tracking = crate::tracking::enable_for_test();
+        let lock = CellRwLock::new(7i32);
+        let _guard = lock.write();
+        let guard_line = line!() - 1;
+        let err = lock.try_read_err().unwrap_err();
+        let payload = std::panic::catch_unwind(|| err.panic()).unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+        // Backtraces are only captured if enabled by `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE`
+        if Backtrace::capture().status() == BacktraceStatus::Captured {
+            let backtraces = err.held_backtraces().collect::<Vec<_>>();
+            assert_eq!(backtraces.len(), 1);
+            let (location, backtrace) = backtraces[0];
+            assert_eq!((location.file(), location.line()), (file!(), guard_line));
+            assert!(
+                backtrace.to_string().contains("held_backtraces"),
+                "{backtrace}"
+            );
+            let section = format!("\n\nBacktrace of the borrow at {location}:\n{backtrace}");
+            assert!(message.ends_with(&section), "{message}");
+        } else {
+            assert_eq!(err.held_backtraces().count(), 0);
+            assert!(!message.contains("Backtrace of the borrow"), "{message}");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn poisoning() {
+        fn assert_unwind_safe<T: core::panic::RefUnwindSafe + core::panic::UnwindSafe>(_: &T) {}
+        let lock = CellRwLock::new(7i32);
+        assert_unwind_safe(&lock);
+        let _ = std::panic::catch_unwind(|| {
+            let _guard = lock.read();
+            panic!("reader");
+        });
+        assert!(!lock.is_poisoned());
+        let _ = std::panic::catch_unwind(|| {
+            let _guard = lock.write();
+            panic!("writer");
+        });
+        assert!(lock.is_poisoned());
+        lock.clear_poison();
+        assert!(!lock.is_poisoned());
+        // Like std, a borrow acquired while already panicking does not poison
+        struct WriteOnDrop<'a>(&'a CellRwLock<i32>);
+        impl Drop for WriteOnDrop<'_> {
+            fn drop(&mut self) {
+                *self.0.write() += 1;
+            }
+        }
+        let _ = std::panic::catch_unwind(|| {
+            let _writer = WriteOnDrop(&lock);
+            panic!("unwinding");
+        });
+        assert!(!lock.is_poisoned());
+        assert_eq!(*lock.read(), 8);
+
+        let mutex = CellMutex::new(7i32);
+        assert_unwind_safe(&mutex);
+        let _ = std::panic::catch_unwind(|| {
+            let _guard = mutex.lock();
+            panic!("mutex");
+        });
+        assert!(mutex.is_poisoned());
+        *mutex.lock() = 8;
+        mutex.clear_poison();
+        assert!(!mutex.is_poisoned());
+    }
+
+    #[test]
+    fn map_split() {
+        let lock = CellRwLock::new(([1i32, 2], [3i32, 4]));
+        {
+            let guard = lock.write();
+            let (mut left, right) = CellRwLockWriteGuard::map_split(guard, |(a, b)| (a, b));
+            let (mut first, mut second) =
+                MappedCellRwLockWriteGuard::map_split(right, |[c, d]| (c, d));
+            left[0] = 10;
+            *first += 10;
+            *second += 10;
+            let state = lock.borrow_state();
+            assert_eq!(state.status(), BorrowStatus::Exclusive);
+            assert_eq!(state.count(), 3);
+            assert!(lock.try_read().is_none());
+            drop(left);
+            drop(first);
+            assert!(lock.is_locked_exclusive());
+        }
+        assert!(!lock.is_locked());
+        {
+            let guard = lock.read();
+            let (left, right) = CellRwLockReadGuard::map_split(guard, |(a, b)| (a, b));
+            let (first, _second) = MappedCellRwLockReadGuard::map_split(left, |[a, b]| (a, b));
+            assert_eq!(*first, 10);
+            assert_eq!(*right, [13, 14]);
+            assert_eq!(lock.borrow_state().count(), 3);
+            assert!(lock.try_write().is_none());
+        }
+        assert!(lock.borrow_state().is_unused());
+    }
+
+    #[test]
+    fn map_split_panic() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+        let lock = CellRwLock::new((7i32, 8i32));
+        catch_unwind(AssertUnwindSafe(|| {
+            CellRwLockReadGuard::map_split(lock.read(), |_| -> (&i32, &i32) { panic!("split") })
+        }))
+        .unwrap_err();
+        catch_unwind(AssertUnwindSafe(|| {
+            CellRwLockWriteGuard::map_split(lock.write(), |_| -> (&mut i32, &mut i32) {
+                panic!("split")
+            })
+        }))
+        .unwrap_err();
+        catch_unwind(AssertUnwindSafe(|| {
+            let guard = CellRwLockWriteGuard::map(lock.write(), |val| val);
+            MappedCellRwLockWriteGuard::map_split(guard, |_| -> (&mut i32, &mut i32) {
+                panic!("split")
+            })
+        }))
+        .unwrap_err();
+        assert!(lock.borrow_state().is_unused());
+        assert_eq!(*lock.write(), (7, 8));
+    }
+
+    #[test]
+    fn leak() {
+        let mut lock = CellRwLock::new(7i32);
+        let first = CellRwLockReadGuard::leak(lock.read());
+        let second = lock.read_recursive();
+        assert_eq!(*first + *second, 14);
+        drop(second);
+        assert!(lock.try_write().is_none());
+        #[cfg(debug_assertions)]
+        assert_eq!(lock.borrow_state().leaked(), 1);
+        *lock.undo_leak() += 1;
+        assert!(lock.borrow_state().is_unused());
+        *CellRwLockWriteGuard::leak(lock.write()) += 1;
+        assert!(lock.try_read().is_none());
+        assert_eq!(*lock.undo_leak(), 9);
+        // Dropping a lock with leaked borrows is not a bug
+        let value = CellRwLockWriteGuard::map(lock.write(), |x| x);
+        *MappedCellRwLockWriteGuard::leak(value) += 1;
+        drop(lock);
+
+        let mut mutex = CellMutex::new(vec![7i32]);
+        CellMutexGuard::leak(mutex.lock()).push(8);
+        assert!(mutex.try_lock().is_none());
+        assert_eq!(*mutex.undo_leak(), [7, 8]);
+        CellMutexGuard::leak(mutex.lock()).push(9);
+        assert_eq!(mutex.into_inner(), [7, 8, 9]);
+    }
+
+    #[test]
+    #[cfg(all(debug_location, debug_assertions))]
+    fn leak_location() {
+        let _tracking = crate::tracking::enable_for_test();
+        let lock = CellRwLock::new(7i32);
+        let _leaked = CellRwLockReadGuard::leak(lock.read());
+        let _guard = lock.read_recursive();
+        let err = lock.try_write_err().unwrap_err();
+        assert_eq!(err.held_count(), 2);
+        let message = err.to_string();
+        assert!(message.contains("borrowed by 2 reader(s) at"), "{message}");
+        assert!(message.contains("(permanently borrowed at"), "{message}");
+        assert!(!message.contains("unknown"), "{message}");
+        assert_eq!(message.matches(file!()).count(), 3, "{message}");
+        let _second = CellRwLockReadGuard::leak(lock.read_recursive());
+        let message = lock.try_write_err().unwrap_err().to_string();
+        assert!(
+            message.contains("(2 permanently borrowed, first at"),
+            "{message}"
+        );
+    }
+}
@@ -0,0 +1,94 @@
+//! A global switch controlling whether borrow locations are recorded.
+//!
+//! Location tracking is compiled in by the `debug-location` feature (in debug mode),
+//! or by the `debug-location-release` feature (in every mode).
+//! This switch controls whether it is actually used at runtime,
+//! so tracking can be compiled into a release build and only turned on
+//! while investigating a bug.
+//!
+//! The switch is enabled by default in debug mode, and disabled by default in release mode.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether borrow locations are currently recorded.
+static LOCATION_TRACKING: AtomicBool = AtomicBool::new(cfg!(debug_assertions));
+
+/// Enable or disable recording the locations of new borrows.
+///
+/// Borrows that are already active keep their recorded location (if any).
+/// Borrows acquired while tracking is disabled are still counted,
+/// but are reported as being at an unknown location.
+///
+/// This has no effect unless location tracking is compiled in,
+/// see [location_tracking_available].
+///
+/// The switch is global, and shared between all threads.
+pub fn set_location_tracking(enabled: bool) {
+    LOCATION_TRACKING.store(enabled, Ordering::Relaxed);
+}
+
+/// Check if the locations of new borrows are currently recorded.
+///
+/// This is always `false` unless location tracking is compiled in.
+#[inline]
+pub fn location_tracking_enabled() -> bool {
+    location_tracking_available() && LOCATION_TRACKING.load(Ordering::Relaxed)
+}
+
+/// Check if location tracking is compiled in,
+/// so that it can be enabled with [set_location_tracking].
+#[inline]
+pub const fn location_tracking_available() -> bool {
+    cfg!(debug_location)
+}
+
+/// Serializes the tests which toggle tracking with the tests that depend on it.
+#[cfg(all(test, debug_location))]
+static TEST_SWITCH: std::sync::RwLock<()> = std::sync::RwLock::new(());
+
+/// Enable tracking for a test which depends on it,
+/// keeping it enabled until the returned guard is dropped.
+#[cfg(all(test, debug_location))]
+pub(crate) fn enable_for_test() -> std::sync::RwLockReadGuard<'static, ()> {
+    let guard = TEST_SWITCH.read().unwrap_or_else(|e| e.into_inner());
+    set_location_tracking(true);
+    guard
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(debug_location)]
+    fn toggle_tracking() {
+        use crate::tracked::CellRwLock;
+        let _switch = TEST_SWITCH.write().unwrap_or_else(|e| e.into_inner());
+        set_location_tracking(true);
+        let lock = CellRwLock::new(());
+        let _first = lock.read();
+        set_location_tracking(false);
+        assert!(!location_tracking_enabled());
+        let _second = lock.read_recursive();
+        set_location_tracking(true);
+        let _third = lock.read_recursive();
+        let state = lock.borrow_state();
+        assert_eq!(state.count(), 3);
+        assert_eq!(state.locations().count(), 2);
+        let err = lock.try_write_err().unwrap_err();
+        assert!(
+            err.to_string().contains("and 1 unknown location(s)"),
+            "{err}"
+        );
+        drop(_third);
+        drop(_second);
+        assert_eq!(lock.borrow_state().locations().count(), 1);
+    }
+
+    #[test]
+    #[cfg(not(debug_location))]
+    fn unavailable_tracking() {
+        set_location_tracking(true);
+        assert!(!location_tracking_enabled());
+    }
+}